@@ -0,0 +1,143 @@
+use alloc::boxed::Box;
+
+/// An allocator whose boxes can be stored in an `AtomicOption`.
+///
+/// # Safety
+///
+/// `from_raw` must accept any pointer produced by `into_raw` for a box of the same allocator type,
+/// whichever value of the type allocated it. `AtomicOption` discards the allocator of every box
+/// stored into it and frees the box with its own.
+pub unsafe trait BoxAllocator: Clone {
+    type Box<T>;
+
+    fn into_raw<T>(data: Self::Box<T>) -> (*mut T, Self);
+
+    /// # Safety
+    ///
+    /// `addr` must come from `into_raw` and must not be used again.
+    unsafe fn from_raw<T>(addr: *mut T, alloc: Self) -> Self::Box<T>;
+}
+
+/// The global allocator, whose boxes are plain `Box<T>`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Global;
+
+unsafe impl BoxAllocator for Global {
+    type Box<T> = Box<T>;
+
+    #[inline(always)]
+    fn into_raw<T>(data: Box<T>) -> (*mut T, Global) {
+        (Box::into_raw(data), Global)
+    }
+
+    #[inline(always)]
+    unsafe fn from_raw<T>(addr: *mut T, _alloc: Global) -> Box<T> {
+        Box::from_raw(addr)
+    }
+}
+
+/// An allocator any value of which can free memory allocated by any other value of the type, such
+/// as a handle to a single global heap.
+///
+/// Implementing this makes the allocator usable with `AtomicOption`.
+///
+/// # Safety
+///
+/// Deallocating through any value of the type must be sound for memory allocated by any other
+/// value of it.
+#[cfg(any(feature = "allocator_api", feature = "allocator-api2"))]
+pub unsafe trait InterchangeableAllocator: Allocator + Clone {}
+
+#[cfg(feature = "allocator_api")]
+pub use alloc::alloc::Allocator;
+
+#[cfg(feature = "allocator_api")]
+unsafe impl InterchangeableAllocator for alloc::alloc::Global {}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<A: InterchangeableAllocator> BoxAllocator for A {
+    type Box<T> = Box<T, A>;
+
+    #[inline(always)]
+    fn into_raw<T>(data: Box<T, A>) -> (*mut T, A) {
+        Box::into_raw_with_allocator(data)
+    }
+
+    #[inline(always)]
+    unsafe fn from_raw<T>(addr: *mut T, alloc: A) -> Box<T, A> {
+        Box::from_raw_in(addr, alloc)
+    }
+}
+
+#[cfg(all(feature = "allocator-api2", not(feature = "allocator_api")))]
+pub use allocator_api2::alloc::Allocator;
+
+#[cfg(all(feature = "allocator-api2", not(feature = "allocator_api")))]
+unsafe impl InterchangeableAllocator for allocator_api2::alloc::Global {}
+
+#[cfg(all(feature = "allocator-api2", not(feature = "allocator_api")))]
+unsafe impl<A: InterchangeableAllocator> BoxAllocator for A {
+    type Box<T> = allocator_api2::boxed::Box<T, A>;
+
+    #[inline(always)]
+    fn into_raw<T>(data: Self::Box<T>) -> (*mut T, A) {
+        allocator_api2::boxed::Box::into_raw_with_allocator(data)
+    }
+
+    #[inline(always)]
+    unsafe fn from_raw<T>(addr: *mut T, alloc: A) -> Self::Box<T> {
+        allocator_api2::boxed::Box::from_raw_in(addr, alloc)
+    }
+}
+
+#[cfg(all(test, any(feature = "allocator_api", feature = "allocator-api2")))]
+mod tests {
+    #[cfg(feature = "allocator_api")]
+    use alloc::alloc::{AllocError, Allocator, Global};
+    #[cfg(feature = "allocator_api")]
+    use alloc::boxed::Box;
+    use core::alloc::Layout;
+    use core::ptr::NonNull;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[cfg(not(feature = "allocator_api"))]
+    use allocator_api2::alloc::{AllocError, Allocator, Global};
+    #[cfg(not(feature = "allocator_api"))]
+    use allocator_api2::boxed::Box;
+
+    use super::InterchangeableAllocator;
+    use crate::AtomicOption;
+
+    #[derive(Clone, Copy)]
+    struct Counting<'a>(&'a AtomicUsize);
+
+    unsafe impl InterchangeableAllocator for Counting<'_> {}
+
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.fetch_sub(1, Ordering::Relaxed);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_custom_allocator() {
+        let live = AtomicUsize::new(0);
+        let alloc = Counting(&live);
+        let opt = AtomicOption::new_in(Some(Box::new_in(0, alloc)), alloc);
+        assert_eq!(live.load(Ordering::Relaxed), 1);
+        let old = opt.swap(Some(Box::new_in(1, alloc))).unwrap();
+        assert_eq!(*old, 0);
+        drop(old);
+        assert_eq!(live.load(Ordering::Relaxed), 1);
+        assert!(opt.try_store(Box::new_in(2, alloc)).is_err());
+        assert_eq!(live.load(Ordering::Relaxed), 1);
+        drop(opt);
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+    }
+}
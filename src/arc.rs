@@ -0,0 +1,210 @@
+use core::marker::PhantomData;
+use core::ptr::null_mut;
+use std::sync::Arc;
+
+use crate::atomic::{AtomicPtr, Ordering};
+use crate::hazard::Domain;
+use crate::PhantomUnsync;
+
+/// An atomic `Option<Arc<T>>` whose loads hand out new references to the current value.
+///
+/// Loads upgrade the current value under a hazard pointer of a `Domain`. Writers hand the slot's
+/// own reference to a replaced value back as soon as no load is still upgrading it, so dropping
+/// that reference drops `T` right away if nobody else holds it.
+pub struct AtomicOptionArc<T> {
+    inner: AtomicPtr<T>,
+    domain: &'static Domain,
+    _phantom: PhantomUnsync<T>,
+}
+
+impl<T> AtomicOptionArc<T> {
+    #[inline(always)]
+    pub fn new(data: Option<Arc<T>>) -> AtomicOptionArc<T> {
+        Self::with_domain(data, Domain::global())
+    }
+
+    #[inline(always)]
+    pub fn with_domain(data: Option<Arc<T>>, domain: &'static Domain) -> AtomicOptionArc<T> {
+        AtomicOptionArc {
+            inner: AtomicPtr::new(into_raw(data)),
+            domain,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn load(&self) -> Option<Arc<T>> {
+        let hazard = self.domain.hazard_pointer();
+        let addr = hazard.protect(&self.inner);
+        unsafe { clone_raw(addr) }
+    }
+
+    pub fn swap(&self, new: Option<Arc<T>>) -> Option<Arc<T>> {
+        let addr = self.inner.swap(into_raw(new), Ordering::SeqCst);
+        unsafe { self.release(addr) }
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<Arc<T>> {
+        self.swap(None)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<Arc<T>>) {
+        drop(self.swap(new))
+    }
+
+    /// Stores `new` if the slot still holds `current`, compared by pointer.
+    ///
+    /// Returns the previous value either way; the swap happened iff it is `current`.
+    pub fn compare_and_swap(
+        &self,
+        current: Option<&Arc<T>>,
+        new: Option<Arc<T>>,
+    ) -> Option<Arc<T>> {
+        let current = current.map_or(null_mut(), |c| Arc::as_ptr(c) as *mut T);
+        let new = into_raw(new);
+        let hazard = self.domain.hazard_pointer();
+        loop {
+            let addr = hazard.protect(&self.inner);
+            if addr != current {
+                let prev = unsafe { clone_raw(addr) };
+                // Dropping `new` may run code that writes to this slot and waits for `addr` to be
+                // unprotected, so the hazard has to go first.
+                drop(hazard);
+                drop(unsafe { from_raw(new) });
+                return prev;
+            }
+            if self
+                .inner
+                .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                drop(hazard);
+                return unsafe { self.release(addr) };
+            }
+        }
+    }
+
+    /// Takes over the slot's reference to `addr`, which has just been unlinked.
+    #[inline(always)]
+    unsafe fn release(&self, addr: *mut T) -> Option<Arc<T>> {
+        if !addr.is_null() {
+            self.domain.wait_unprotected(addr.cast());
+        }
+        from_raw(addr)
+    }
+}
+
+unsafe impl<T> Sync for AtomicOptionArc<T> where T: Send + Sync {}
+unsafe impl<T> Send for AtomicOptionArc<T> where T: Send + Sync {}
+
+impl<T> Drop for AtomicOptionArc<T> {
+    fn drop(&mut self) {
+        drop(unsafe { from_raw(*self.inner.get_mut()) });
+    }
+}
+
+#[inline(always)]
+fn into_raw<T>(data: Option<Arc<T>>) -> *mut T {
+    data.map_or(null_mut(), |data| Arc::into_raw(data) as *mut T)
+}
+
+#[inline(always)]
+unsafe fn from_raw<T>(addr: *mut T) -> Option<Arc<T>> {
+    if addr.is_null() {
+        None
+    } else {
+        Some(Arc::from_raw(addr))
+    }
+}
+
+/// Creates a new reference to `addr`, which must be null or kept alive by the caller.
+#[inline(always)]
+unsafe fn clone_raw<T>(addr: *mut T) -> Option<Arc<T>> {
+    if !addr.is_null() {
+        Arc::increment_strong_count(addr);
+    }
+    from_raw(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::{thread, vec::Vec};
+
+    use super::AtomicOptionArc;
+
+    #[test]
+    fn test_simple() {
+        let opt = AtomicOptionArc::new(None);
+        assert_eq!(opt.load(), None);
+        let zero = Arc::new(0);
+        opt.store(Some(zero.clone()));
+        assert!(Arc::ptr_eq(&opt.load().unwrap(), &zero));
+        assert_eq!(opt.swap(Some(Arc::new(1))), Some(zero.clone()));
+        let one = opt.load().unwrap();
+        let prev = opt.compare_and_swap(Some(&zero), Some(Arc::new(2)));
+        assert!(Arc::ptr_eq(&prev.unwrap(), &one));
+        let prev = opt.compare_and_swap(Some(&one), Some(Arc::new(2)));
+        assert!(Arc::ptr_eq(&prev.unwrap(), &one));
+        assert_eq!(opt.take(), Some(Arc::new(2)));
+        assert_eq!(opt.compare_and_swap(None, Some(Arc::new(3))), None);
+        assert_eq!(opt.load(), Some(Arc::new(3)));
+    }
+
+    #[test]
+    fn test_unique_after_take() {
+        let opt = AtomicOptionArc::new(Some(Arc::new(0)));
+        let loaded = opt.load().unwrap();
+        let taken = opt.take().unwrap();
+        assert_eq!(Arc::strong_count(&taken), 2);
+        drop(loaded);
+        assert_eq!(Arc::try_unwrap(taken), Ok(0));
+    }
+
+    #[test]
+    fn test_reentrant_drop() {
+        struct Node(Option<&'static AtomicOptionArc<Node>>);
+
+        impl Drop for Node {
+            fn drop(&mut self) {
+                if let Some(opt) = self.0 {
+                    opt.store(None);
+                }
+            }
+        }
+
+        let opt = Box::leak(Box::new(AtomicOptionArc::new(Some(Arc::new(Node(None))))));
+        let prev = opt.compare_and_swap(None, Some(Arc::new(Node(Some(opt)))));
+        assert!(prev.is_some());
+        assert!(opt.load().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_loads() {
+        let opt = AtomicOptionArc::new(Some(Arc::new(0)));
+        let stop = AtomicBool::new(false);
+        let replaced: Vec<_> = thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        let v = *opt.load().unwrap();
+                        assert!(v >= last);
+                        last = v;
+                    }
+                });
+            }
+            let replaced = (1..1000)
+                .map(|i| opt.swap(Some(Arc::new(i))).unwrap())
+                .collect();
+            stop.store(true, Ordering::Relaxed);
+            replaced
+        });
+        for (i, v) in replaced.into_iter().enumerate() {
+            assert_eq!(Arc::try_unwrap(v), Ok(i));
+        }
+    }
+}
@@ -0,0 +1,11 @@
+#![allow(unused_imports)]
+
+#[cfg(not(feature = "portable-atomic"))]
+pub(crate) use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "portable-atomic")]
+pub(crate) use portable_atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+#[cfg(all(not(feature = "portable-atomic"), target_has_atomic = "64"))]
+pub(crate) use core::sync::atomic::AtomicU64;
+#[cfg(feature = "portable-atomic")]
+pub(crate) use portable_atomic::AtomicU64;
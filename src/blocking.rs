@@ -0,0 +1,229 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::boxed::Box;
+use std::time::{Duration, Instant};
+
+use crate::allocator::{BoxAllocator, Global};
+use crate::atomic::{AtomicUsize, Ordering};
+use crate::{park, AtomicOption};
+
+/// An `AtomicOption` whose value can be waited for, by parking the thread or as a future.
+///
+/// Writers only look for threads to wake while some are waiting on this slot, so a slot nobody
+/// waits on costs one extra relaxed load per successful store.
+pub struct BlockingAtomicOption<T, A: BoxAllocator = Global> {
+    inner: AtomicOption<T, A>,
+    waiters: AtomicUsize,
+}
+
+impl<T> BlockingAtomicOption<T> {
+    #[inline(always)]
+    pub fn new(data: Option<Box<T>>) -> BlockingAtomicOption<T> {
+        Self::new_in(data, Global)
+    }
+}
+
+impl<T, A: BoxAllocator> BlockingAtomicOption<T, A> {
+    /// Creates a slot whose values are freed through `alloc`.
+    #[inline(always)]
+    pub fn new_in(data: Option<A::Box<T>>, alloc: A) -> BlockingAtomicOption<T, A> {
+        BlockingAtomicOption {
+            inner: AtomicOption::new_in(data, alloc),
+            waiters: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline(always)]
+    pub fn swap(&self, new: Option<A::Box<T>>) -> Option<A::Box<T>> {
+        let publish = new.is_some();
+        let old = self.inner.swap(new);
+        if publish {
+            self.notify();
+        }
+        old
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<A::Box<T>> {
+        self.inner.take()
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<A::Box<T>>) {
+        drop(self.swap(new))
+    }
+
+    /// See `AtomicOption::compare_exchange`.
+    #[allow(clippy::type_complexity)]
+    #[inline(always)]
+    pub fn compare_exchange(
+        &self,
+        current: *const T,
+        new: Option<A::Box<T>>,
+    ) -> Result<Option<A::Box<T>>, Option<A::Box<T>>> {
+        let publish = new.is_some();
+        let res = self.inner.compare_exchange(current, new);
+        if publish && res.is_ok() {
+            self.notify();
+        }
+        res
+    }
+
+    #[inline(always)]
+    pub fn try_store(&self, new: A::Box<T>) -> Result<(), A::Box<T>> {
+        self.inner.try_store(new).map(|()| self.notify())
+    }
+
+    #[inline(always)]
+    pub fn load_ptr(&self) -> *const T {
+        self.inner.load_ptr()
+    }
+
+    #[inline(always)]
+    pub fn is_some(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    #[inline(always)]
+    pub fn is_none(&self) -> bool {
+        self.inner.is_none()
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.inner.get_mut()
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> Option<A::Box<T>> {
+        self.inner.into_inner()
+    }
+
+    /// Takes the value, parking the current thread until one is stored.
+    pub fn take_blocking(&self) -> A::Box<T> {
+        self.wait(None).unwrap()
+    }
+
+    /// Takes the value, parking the current thread for at most `timeout` until one is stored.
+    pub fn take_timeout(&self, timeout: Duration) -> Option<A::Box<T>> {
+        self.wait(Instant::now().checked_add(timeout))
+    }
+
+    /// Returns a future that takes the value once one is stored.
+    ///
+    /// The value is only taken when the future completes, so dropping it never loses a value.
+    #[inline(always)]
+    pub fn take_async(&self) -> Take<'_, T, A> {
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        Take {
+            opt: self,
+            registration: None,
+        }
+    }
+
+    fn wait(&self, deadline: Option<Instant>) -> Option<A::Box<T>> {
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        let res = park::wait(self.inner.key(), deadline, || self.inner.take());
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        res
+    }
+
+    /// Wakes the waiters of this slot, if there are any.
+    ///
+    /// A waiter counts itself before its final `take`. If that `take` missed the value, it came
+    /// before the writer's swap in the slot's modification order, so the swap acquires the count.
+    #[inline(always)]
+    fn notify(&self) {
+        if self.waiters.load(Ordering::Relaxed) != 0 {
+            park::notify(self.inner.key());
+        }
+    }
+}
+
+impl<T> Default for BlockingAtomicOption<T> {
+    #[inline(always)]
+    fn default() -> BlockingAtomicOption<T> {
+        BlockingAtomicOption::new(None)
+    }
+}
+
+/// Future returned by `BlockingAtomicOption::take_async`.
+pub struct Take<'a, T, A: BoxAllocator = Global> {
+    opt: &'a BlockingAtomicOption<T, A>,
+    registration: Option<usize>,
+}
+
+impl<T, A: BoxAllocator> Future for Take<'_, T, A> {
+    type Output = A::Box<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<A::Box<T>> {
+        let this = self.get_mut();
+        let inner = &this.opt.inner;
+        park::poll_wait(inner.key(), &mut this.registration, cx, || inner.take())
+    }
+}
+
+impl<T, A: BoxAllocator> Drop for Take<'_, T, A> {
+    fn drop(&mut self) {
+        park::cancel(self.opt.inner.key(), self.registration.take());
+        self.opt.waiters.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::future::Future;
+    use std::task::{Context, Waker};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::BlockingAtomicOption;
+    use crate::tests::block_on;
+
+    #[test]
+    fn test_take_blocking() {
+        let opt = BlockingAtomicOption::<i64>::new(None);
+        assert_eq!(opt.take_timeout(Duration::from_millis(10)), None);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    assert_eq!(opt.take_blocking(), Box::new(i));
+                }
+            });
+            for i in 0..100 {
+                while opt.try_store(Box::new(i)).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+        opt.store(Some(Box::new(0)));
+        assert_eq!(opt.take_timeout(Duration::from_secs(1)), Some(Box::new(0)));
+    }
+
+    #[test]
+    fn test_take_async() {
+        let opt = BlockingAtomicOption::<i64>::new(None);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    assert_eq!(block_on(opt.take_async()), Box::new(i));
+                }
+            });
+            for i in 0..100 {
+                while opt.try_store(Box::new(i)).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut fut = Box::pin(opt.take_async());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(fut.as_mut().poll(&mut cx).is_pending());
+        drop(fut);
+        assert_eq!(opt.waiters.load(std::sync::atomic::Ordering::Relaxed), 0);
+        opt.store(Some(Box::new(0)));
+        assert_eq!(opt.take(), Some(Box::new(0)));
+    }
+}
@@ -0,0 +1,65 @@
+use alloc::boxed::Box;
+
+use crate::AtomicOption;
+
+/// An `AtomicOption` for unsized values such as `Box<dyn Trait>`, `Box<[T]>` and `Box<str>`.
+///
+/// The fat box is kept behind an internal thin allocation so that it can be swapped through a
+/// single pointer.
+pub struct AtomicOptionBox<T: ?Sized> {
+    inner: AtomicOption<Box<T>>,
+}
+
+impl<T: ?Sized> AtomicOptionBox<T> {
+    #[inline(always)]
+    pub fn new(data: Option<Box<T>>) -> AtomicOptionBox<T> {
+        AtomicOptionBox {
+            inner: AtomicOption::new(data.map(Box::new)),
+        }
+    }
+
+    #[inline(always)]
+    pub fn swap(&self, new: Option<Box<T>>) -> Option<Box<T>> {
+        self.inner.swap(new.map(Box::new)).map(|data| *data)
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<Box<T>> {
+        self.swap(None)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<Box<T>>) {
+        drop(self.swap(new))
+    }
+
+    #[inline(always)]
+    pub fn try_store(&self, new: Box<T>) -> Result<(), Box<T>> {
+        self.inner.try_store(Box::new(new)).map_err(|new| *new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec;
+
+    use super::AtomicOptionBox;
+
+    #[test]
+    fn test_unsized() {
+        let opt = AtomicOptionBox::<dyn Fn() -> i32>::new(None);
+        opt.store(Some(Box::new(|| 0)));
+        assert_eq!(opt.swap(Some(Box::new(|| 1))).unwrap()(), 0);
+        assert!(opt.try_store(Box::new(|| 2)).is_err());
+        assert_eq!(opt.take().unwrap()(), 1);
+
+        let opt = AtomicOptionBox::<[u8]>::new(Some(vec![0, 1].into()));
+        assert_eq!(opt.swap(Some(Box::new([2]))).as_deref(), Some(&[0, 1][..]));
+
+        let opt = AtomicOptionBox::<str>::new(None);
+        assert_eq!(opt.try_store("hello".into()), Ok(()));
+        assert_eq!(opt.take().as_deref(), Some("hello"));
+        assert_eq!(opt.take(), None);
+    }
+}
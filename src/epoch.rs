@@ -0,0 +1,247 @@
+use core::marker::PhantomData;
+use core::ptr::null_mut;
+use std::boxed::Box;
+
+use crossbeam_epoch::default_collector;
+pub use crossbeam_epoch::{pin, Guard};
+
+use crate::atomic::{AtomicPtr, Ordering};
+use crate::PhantomUnsync;
+
+/// An `AtomicOption` whose replaced values are retired to an epoch-based collector, so the current
+/// value can be borrowed by many readers at once.
+///
+/// Every method taking a `Guard` panics unless it was pinned on the default collector, which is
+/// the one `pin` uses and the one replaced values are retired to.
+pub struct EpochAtomicOption<T> {
+    inner: AtomicPtr<T>,
+    _phantom: PhantomUnsync<T>,
+}
+
+impl<T> EpochAtomicOption<T> {
+    #[inline(always)]
+    pub fn new(data: Option<Box<T>>) -> EpochAtomicOption<T> {
+        EpochAtomicOption {
+            inner: AtomicPtr::new(into_raw(data)),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn load<'g>(&'g self, guard: &'g Guard) -> Option<&'g T> {
+        check_guard(guard);
+        unsafe { self.inner.load(Ordering::Acquire).as_ref() }
+    }
+}
+
+impl<T: Send + 'static> EpochAtomicOption<T> {
+    /// Replaces the value, returning the old one borrowed for as long as `guard` is pinned.
+    #[inline(always)]
+    pub fn swap<'g>(&'g self, new: Option<Box<T>>, guard: &'g Guard) -> Option<&'g T> {
+        check_guard(guard);
+        let addr = self.inner.swap(into_raw(new), Ordering::AcqRel);
+        unsafe { retire(addr, guard) }
+    }
+
+    #[inline(always)]
+    pub fn take<'g>(&'g self, guard: &'g Guard) -> Option<&'g T> {
+        self.swap(None, guard)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<Box<T>>) {
+        self.swap(new, &pin());
+    }
+
+    /// Takes the value only if `pred` approves of it.
+    ///
+    /// The value cannot be freed while `guard` is pinned, so if it is still in the slot after
+    /// `pred` returns, it is the one `pred` saw.
+    pub fn take_if<'g, F>(&'g self, mut pred: F, guard: &'g Guard) -> Option<&'g T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        check_guard(guard);
+        let mut addr = self.inner.load(Ordering::Acquire);
+        loop {
+            if !pred(unsafe { addr.as_ref() }?) {
+                return None;
+            }
+            match self
+                .inner
+                .compare_exchange(addr, null_mut(), Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return unsafe { retire(addr, guard) },
+                Err(current) => addr = current,
+            }
+        }
+    }
+
+    /// Installs the box `f` computes from the current value, calling `f` again on the fresh value
+    /// each time the exchange loses to another writer.
+    ///
+    /// The box from a lost round is passed to the next call for reuse. On success the replaced
+    /// value stays readable for as long as `guard`; if `f` returns `None` the current value comes
+    /// back as `Err`.
+    #[allow(clippy::type_complexity)]
+    pub fn fetch_update<'g, F>(
+        &'g self,
+        mut f: F,
+        guard: &'g Guard,
+    ) -> Result<Option<&'g T>, Option<&'g T>>
+    where
+        F: FnMut(Option<&T>, Option<Box<T>>) -> Option<Box<T>>,
+    {
+        check_guard(guard);
+        let mut addr = self.inner.load(Ordering::Acquire);
+        let mut rejected = None;
+        loop {
+            let Some(new) = f(unsafe { addr.as_ref() }, rejected.take()) else {
+                return Err(unsafe { addr.as_ref() });
+            };
+            let new = Box::into_raw(new);
+            match self
+                .inner
+                .compare_exchange(addr, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(unsafe { retire(addr, guard) }),
+                Err(current) => {
+                    rejected = Some(unsafe { Box::from_raw(new) });
+                    addr = current;
+                }
+            }
+        }
+    }
+}
+
+unsafe impl<T> Sync for EpochAtomicOption<T> where T: Send + Sync {}
+unsafe impl<T> Send for EpochAtomicOption<T> where T: Send {}
+
+impl<T> Drop for EpochAtomicOption<T> {
+    fn drop(&mut self) {
+        let addr = *self.inner.get_mut();
+        if !addr.is_null() {
+            drop(unsafe { Box::from_raw(addr) });
+        }
+    }
+}
+
+/// Only a guard of the default collector keeps values retired through `retire` alive.
+#[inline(always)]
+fn check_guard(guard: &Guard) {
+    assert!(
+        guard.collector() == Some(default_collector()),
+        "guard must be pinned on the default collector"
+    );
+}
+
+#[inline(always)]
+fn into_raw<T>(data: Option<Box<T>>) -> *mut T {
+    data.map_or(null_mut(), Box::into_raw)
+}
+
+/// Defers freeing `addr` until every currently pinned thread has unpinned.
+///
+/// `addr` must be null or a box that has just been unlinked from the slot.
+#[inline(always)]
+unsafe fn retire<T: Send + 'static>(addr: *mut T, guard: &Guard) -> Option<&T> {
+    if addr.is_null() {
+        return None;
+    }
+    let data = RetiredBox(addr);
+    guard.defer_unchecked(move || drop(data));
+    Some(&*addr)
+}
+
+struct RetiredBox<T>(*mut T);
+
+impl<T> Drop for RetiredBox<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.0) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::{thread, vec, vec::Vec};
+
+    use crossbeam_epoch::Collector;
+
+    use super::{pin, EpochAtomicOption};
+
+    #[test]
+    fn test_simple() {
+        let opt = EpochAtomicOption::new(None);
+        let guard = pin();
+        assert_eq!(opt.load(&guard), None);
+        opt.store(Some(Box::new(0)));
+        assert_eq!(opt.load(&guard), Some(&0));
+        assert_eq!(opt.swap(Some(Box::new(1)), &guard), Some(&0));
+        assert_eq!(opt.load(&guard), Some(&1));
+        assert_eq!(opt.take(&guard), Some(&1));
+        assert_eq!(opt.load(&guard), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "guard must be pinned on the default collector")]
+    fn test_foreign_guard() {
+        let opt = EpochAtomicOption::new(Some(Box::new(0)));
+        let handle = Collector::new().register();
+        opt.load(&handle.pin());
+    }
+
+    #[test]
+    fn test_take_if() {
+        let opt = EpochAtomicOption::new(Some(Box::new(0)));
+        let guard = pin();
+        assert_eq!(opt.take_if(|v| *v > 0, &guard), None);
+        assert_eq!(opt.load(&guard), Some(&0));
+        assert_eq!(opt.take_if(|v| *v == 0, &guard), Some(&0));
+        assert_eq!(opt.take_if(|_| unreachable!(), &guard), None);
+    }
+
+    #[test]
+    fn test_fetch_update() {
+        let opt = EpochAtomicOption::new(None);
+        let guard = pin();
+        let f = |v: Option<&i32>, rejected: Option<Box<i32>>| {
+            assert!(rejected.is_none());
+            Some(Box::new(v.map_or(0, |v| v + 1)))
+        };
+        assert_eq!(opt.fetch_update(f, &guard), Ok(None));
+        assert_eq!(opt.fetch_update(f, &guard), Ok(Some(&0)));
+        assert_eq!(opt.fetch_update(|_, _| None, &guard), Err(Some(&1)));
+        assert_eq!(opt.load(&guard), Some(&1));
+    }
+
+    #[test]
+    fn test_concurrent_readers() {
+        let opt = Arc::new(EpochAtomicOption::new(Some(Box::new(vec![0u64; 16]))));
+        let stop = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let opt = opt.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let guard = pin();
+                        if let Some(v) = opt.load(&guard) {
+                            assert!(v.iter().all(|x| *x == v[0]));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for i in 1..1000 {
+            opt.store(Some(Box::new(vec![i; 16])));
+        }
+        stop.store(true, Ordering::Relaxed);
+        for h in readers {
+            h.join().unwrap();
+        }
+        assert_eq!(opt.load(&pin()), Some(&vec![999; 16]));
+    }
+}
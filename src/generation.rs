@@ -0,0 +1,393 @@
+use alloc::boxed::Box;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr::null_mut;
+
+use crate::atomic::Ordering;
+use crate::PhantomUnsync;
+
+/// An `AtomicOption` that pairs the pointer with a generation bumped by every modification, so a
+/// `Snapshot` taken before an intervening change no longer compares equal, even if the address is
+/// reused.
+///
+/// The generation wraps around, so a stale snapshot matches again after exactly a multiple of
+/// 2^64 modifications with the `portable-atomic` feature, 2^32 on 32-bit targets, and 2^16 on
+/// 64-bit targets without the feature, where it shares the pointer word.
+///
+/// # Panics
+///
+/// On 64-bit targets without the `portable-atomic` feature, storing a box whose address does not
+/// fit in 48 bits panics, as it can with 5-level paging or pointer tagging.
+pub struct GenerationAtomicOption<T> {
+    inner: cell::Cell<T>,
+    _phantom: PhantomUnsync<T>,
+}
+
+/// The address and generation of a `GenerationAtomicOption` at some point in time.
+pub struct Snapshot<T> {
+    addr: *mut T,
+    generation: u64,
+}
+
+impl<T> GenerationAtomicOption<T> {
+    #[inline(always)]
+    pub fn new(data: Option<Box<T>>) -> GenerationAtomicOption<T> {
+        GenerationAtomicOption {
+            inner: cell::Cell::new(Snapshot {
+                addr: into_raw(data),
+                generation: 0,
+            }),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn load_token(&self) -> Snapshot<T> {
+        self.inner.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub fn swap(&self, new: Option<Box<T>>) -> Option<Box<T>> {
+        let new = into_raw(new);
+        let mut current = self.load_token();
+        loop {
+            match self.inner.compare_exchange_weak(current, current.next(new)) {
+                Ok(_) => return unsafe { from_raw(current.addr) },
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<Box<T>> {
+        self.swap(None)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<Box<T>>) {
+        drop(self.swap(new))
+    }
+
+    /// Stores `new` if nothing has modified the slot since `current` was loaded.
+    ///
+    /// On success the previous value is returned, on failure `new` is handed back untouched.
+    #[inline(always)]
+    pub fn compare_exchange(
+        &self,
+        current: Snapshot<T>,
+        new: Option<Box<T>>,
+    ) -> Result<Option<Box<T>>, Option<Box<T>>> {
+        let new = into_raw(new);
+        match self.inner.compare_exchange(current, current.next(new)) {
+            Ok(_) => Ok(unsafe { from_raw(current.addr) }),
+            Err(_) => Err(unsafe { from_raw(new) }),
+        }
+    }
+}
+
+unsafe impl<T> Sync for GenerationAtomicOption<T> where T: Send {}
+unsafe impl<T> Send for GenerationAtomicOption<T> where T: Send {}
+
+impl<T> Drop for GenerationAtomicOption<T> {
+    fn drop(&mut self) {
+        drop(unsafe { from_raw(self.inner.get_mut().addr) });
+    }
+}
+
+impl<T> Snapshot<T> {
+    /// The address held at the time, only usable as an identity and never dereferenceable.
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *const T {
+        self.addr
+    }
+
+    #[inline(always)]
+    pub fn is_none(&self) -> bool {
+        self.addr.is_null()
+    }
+
+    #[inline(always)]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[inline(always)]
+    fn next(self, addr: *mut T) -> Snapshot<T> {
+        Snapshot {
+            addr,
+            generation: self.generation.wrapping_add(1) & cell::GENERATION_MASK,
+        }
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Snapshot<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Snapshot<T> {}
+
+impl<T> PartialEq for Snapshot<T> {
+    fn eq(&self, other: &Snapshot<T>) -> bool {
+        self.addr == other.addr && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Snapshot<T> {}
+
+impl<T> fmt::Debug for Snapshot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("addr", &self.addr)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+unsafe impl<T> Sync for Snapshot<T> {}
+unsafe impl<T> Send for Snapshot<T> {}
+
+#[inline(always)]
+fn into_raw<T>(data: Option<Box<T>>) -> *mut T {
+    data.map_or(null_mut(), Box::into_raw)
+}
+
+#[inline(always)]
+unsafe fn from_raw<T>(addr: *mut T) -> Option<Box<T>> {
+    if addr.is_null() {
+        None
+    } else {
+        Some(Box::from_raw(addr))
+    }
+}
+
+/// Pointer and generation side by side in a double-word atomic: 128 bits with a 64-bit generation
+/// on 64-bit targets, which needs `portable-atomic`, and 64 bits with a 32-bit generation on
+/// 32-bit ones.
+#[cfg(any(
+    all(target_pointer_width = "64", feature = "portable-atomic"),
+    target_pointer_width = "32"
+))]
+mod cell {
+    use core::marker::PhantomData;
+    use core::ptr;
+
+    use super::Snapshot;
+    use crate::atomic::Ordering;
+
+    #[cfg(target_pointer_width = "64")]
+    type Word = u128;
+    #[cfg(target_pointer_width = "64")]
+    type AtomicWord = portable_atomic::AtomicU128;
+    #[cfg(target_pointer_width = "32")]
+    type Word = u64;
+    #[cfg(target_pointer_width = "32")]
+    type AtomicWord = crate::atomic::AtomicU64;
+
+    pub(super) const GENERATION_MASK: u64 = u64::MAX >> (u64::BITS - usize::BITS);
+
+    pub(super) struct Cell<T> {
+        word: AtomicWord,
+        _phantom: PhantomData<*mut T>,
+    }
+
+    impl<T> Cell<T> {
+        #[inline(always)]
+        pub(super) fn new(snapshot: Snapshot<T>) -> Cell<T> {
+            Cell {
+                word: AtomicWord::new(pack(snapshot)),
+                _phantom: PhantomData,
+            }
+        }
+
+        #[inline(always)]
+        pub(super) fn load(&self, order: Ordering) -> Snapshot<T> {
+            unpack(self.word.load(order))
+        }
+
+        #[inline(always)]
+        pub(super) fn get_mut(&mut self) -> Snapshot<T> {
+            unpack(*self.word.get_mut())
+        }
+
+        #[inline(always)]
+        pub(super) fn compare_exchange(
+            &self,
+            current: Snapshot<T>,
+            new: Snapshot<T>,
+        ) -> Result<Snapshot<T>, Snapshot<T>> {
+            self.word
+                .compare_exchange(
+                    pack(current),
+                    pack(new),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .map(unpack)
+                .map_err(unpack)
+        }
+
+        #[inline(always)]
+        pub(super) fn compare_exchange_weak(
+            &self,
+            current: Snapshot<T>,
+            new: Snapshot<T>,
+        ) -> Result<Snapshot<T>, Snapshot<T>> {
+            self.word
+                .compare_exchange_weak(
+                    pack(current),
+                    pack(new),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .map(unpack)
+                .map_err(unpack)
+        }
+    }
+
+    #[inline(always)]
+    fn pack<T>(snapshot: Snapshot<T>) -> Word {
+        ((snapshot.generation as Word) << usize::BITS) | snapshot.addr.expose_provenance() as Word
+    }
+
+    #[inline(always)]
+    fn unpack<T>(word: Word) -> Snapshot<T> {
+        Snapshot {
+            addr: ptr::with_exposed_provenance_mut(word as usize),
+            generation: (word >> usize::BITS) as u64,
+        }
+    }
+}
+
+/// Generation packed into the high 16 bits of the pointer, which 64-bit targets leave unused
+/// unless they hand out addresses beyond 48 bits or tag pointers.
+#[cfg(all(target_pointer_width = "64", not(feature = "portable-atomic")))]
+mod cell {
+    use core::marker::PhantomData;
+
+    use super::Snapshot;
+    use crate::atomic::{AtomicPtr, Ordering};
+
+    const ADDR_BITS: u32 = 48;
+    const ADDR_MASK: usize = (1 << ADDR_BITS) - 1;
+    pub(super) const GENERATION_MASK: u64 = (1 << (usize::BITS - ADDR_BITS)) - 1;
+
+    pub(super) struct Cell<T> {
+        word: AtomicPtr<T>,
+        _phantom: PhantomData<*mut T>,
+    }
+
+    impl<T> Cell<T> {
+        #[inline(always)]
+        pub(super) fn new(snapshot: Snapshot<T>) -> Cell<T> {
+            Cell {
+                word: AtomicPtr::new(pack(snapshot)),
+                _phantom: PhantomData,
+            }
+        }
+
+        #[inline(always)]
+        pub(super) fn load(&self, order: Ordering) -> Snapshot<T> {
+            unpack(self.word.load(order))
+        }
+
+        #[inline(always)]
+        pub(super) fn get_mut(&mut self) -> Snapshot<T> {
+            unpack(*self.word.get_mut())
+        }
+
+        #[inline(always)]
+        pub(super) fn compare_exchange(
+            &self,
+            current: Snapshot<T>,
+            new: Snapshot<T>,
+        ) -> Result<Snapshot<T>, Snapshot<T>> {
+            self.word
+                .compare_exchange(
+                    pack(current),
+                    pack(new),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .map(unpack)
+                .map_err(unpack)
+        }
+
+        #[inline(always)]
+        pub(super) fn compare_exchange_weak(
+            &self,
+            current: Snapshot<T>,
+            new: Snapshot<T>,
+        ) -> Result<Snapshot<T>, Snapshot<T>> {
+            self.word
+                .compare_exchange_weak(
+                    pack(current),
+                    pack(new),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .map(unpack)
+                .map_err(unpack)
+        }
+    }
+
+    #[inline(always)]
+    fn pack<T>(snapshot: Snapshot<T>) -> *mut T {
+        snapshot.addr.map_addr(|addr| {
+            assert!(
+                addr & !ADDR_MASK == 0,
+                "address does not fit in {ADDR_BITS} bits"
+            );
+            addr | (snapshot.generation as usize) << ADDR_BITS
+        })
+    }
+
+    #[inline(always)]
+    fn unpack<T>(word: *mut T) -> Snapshot<T> {
+        Snapshot {
+            addr: word.map_addr(|addr| addr & ADDR_MASK),
+            generation: (word.addr() >> ADDR_BITS) as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::GenerationAtomicOption;
+
+    #[test]
+    fn test_stale_token() {
+        let opt = GenerationAtomicOption::new(Some(Box::new(0)));
+        let token = opt.load_token();
+        let data = opt.take().unwrap();
+        opt.store(Some(data));
+        let current = opt.load_token();
+        assert_eq!(current.as_ptr(), token.as_ptr());
+        assert_ne!(current, token);
+        assert_eq!(
+            opt.compare_exchange(token, Some(Box::new(1))),
+            Err(Some(Box::new(1)))
+        );
+        assert_eq!(
+            opt.compare_exchange(current, Some(Box::new(1))),
+            Ok(Some(Box::new(0)))
+        );
+        assert_eq!(opt.take(), Some(Box::new(1)));
+        assert!(opt.load_token().is_none());
+    }
+
+    #[test]
+    fn test_generation() {
+        let opt = GenerationAtomicOption::<i32>::new(None);
+        assert_eq!(opt.load_token().generation(), 0);
+        opt.store(Some(Box::new(0)));
+        assert_eq!(opt.take(), Some(Box::new(0)));
+        let token = opt.load_token();
+        assert_eq!(token.generation(), 2);
+        assert!(opt.compare_exchange(token, None).is_ok());
+        assert_eq!(opt.load_token().generation(), 3);
+    }
+}
@@ -0,0 +1,494 @@
+use core::marker::PhantomData;
+use core::mem::take;
+use core::ops::Deref;
+use core::ptr::{null_mut, NonNull};
+use std::boxed::Box;
+use std::sync::Mutex;
+use std::thread;
+use std::vec::Vec;
+
+use crate::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use crate::PhantomUnsync;
+
+const RETIRE_THRESHOLD: usize = 64;
+
+static GLOBAL_DOMAIN: Domain = Domain::new();
+
+/// A set of hazard pointers together with the values retired under their protection.
+pub struct Domain {
+    records: AtomicPtr<Record>,
+    record_count: AtomicUsize,
+    retired: Mutex<Vec<Retired>>,
+}
+
+struct Record {
+    hazard: AtomicPtr<()>,
+    active: AtomicBool,
+    next: *mut Record,
+}
+
+struct Retired {
+    addr: *mut (),
+    drop_fn: unsafe fn(*mut ()),
+}
+
+unsafe impl Send for Retired {}
+
+impl Domain {
+    pub const fn new() -> Domain {
+        Domain {
+            records: AtomicPtr::new(null_mut()),
+            record_count: AtomicUsize::new(0),
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The domain used by `HazardAtomicOption::new`.
+    #[inline(always)]
+    pub fn global() -> &'static Domain {
+        &GLOBAL_DOMAIN
+    }
+
+    /// Frees every retired value that is not protected by a hazard pointer right now.
+    pub fn reclaim(&self) {
+        let retired = take(&mut *self.retired.lock().unwrap());
+        if retired.is_empty() {
+            return;
+        }
+
+        fence(Ordering::SeqCst);
+        let mut protected = Vec::new();
+        let mut record = self.records.load(Ordering::Acquire);
+        while let Some(r) = unsafe { record.as_ref() } {
+            let hazard = r.hazard.load(Ordering::SeqCst);
+            if !hazard.is_null() {
+                protected.push(hazard);
+            }
+            record = r.next;
+        }
+        protected.sort_unstable();
+
+        let mut remain = Vec::new();
+        for r in retired {
+            if protected.binary_search(&r.addr).is_ok() {
+                remain.push(r);
+            } else {
+                unsafe { (r.drop_fn)(r.addr) };
+            }
+        }
+        if !remain.is_empty() {
+            self.retired.lock().unwrap().append(&mut remain);
+        }
+    }
+
+    pub(crate) fn hazard_pointer(&self) -> HazardPointer<'_> {
+        let mut record = self.records.load(Ordering::Acquire);
+        while let Some(r) = unsafe { record.as_ref() } {
+            if !r.active.load(Ordering::Relaxed)
+                && r.active
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return HazardPointer { record: r };
+            }
+            record = r.next;
+        }
+
+        let new = Box::into_raw(Box::new(Record {
+            hazard: AtomicPtr::new(null_mut()),
+            active: AtomicBool::new(true),
+            next: null_mut(),
+        }));
+        let mut head = self.records.load(Ordering::Acquire);
+        loop {
+            unsafe { (*new).next = head };
+            match self
+                .records
+                .compare_exchange_weak(head, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        self.record_count.fetch_add(1, Ordering::Relaxed);
+        HazardPointer {
+            record: unsafe { &*new },
+        }
+    }
+
+    /// Waits until no hazard pointer of this domain protects `addr`.
+    ///
+    /// `addr` must already be unreachable for new readers, so only readers that protected it
+    /// before it was unlinked can hold it up, and only until they drop their hazard pointer.
+    pub(crate) fn wait_unprotected(&self, addr: *mut ()) {
+        fence(Ordering::SeqCst);
+        let mut record = self.records.load(Ordering::Acquire);
+        while let Some(r) = unsafe { record.as_ref() } {
+            while r.hazard.load(Ordering::SeqCst) == addr {
+                thread::yield_now();
+            }
+            record = r.next;
+        }
+    }
+
+    /// Schedules `addr` to be freed once no hazard pointer of this domain protects it.
+    ///
+    /// `addr` must already be unreachable for new readers and `drop_fn` must be safe to call on
+    /// any thread.
+    pub(crate) unsafe fn retire(&self, addr: *mut (), drop_fn: unsafe fn(*mut ())) {
+        let len = {
+            let mut retired = self.retired.lock().unwrap();
+            retired.push(Retired { addr, drop_fn });
+            retired.len()
+        };
+        if len >= RETIRE_THRESHOLD.max(2 * self.record_count.load(Ordering::Relaxed)) {
+            self.reclaim();
+        }
+    }
+}
+
+impl Default for Domain {
+    fn default() -> Domain {
+        Domain::new()
+    }
+}
+
+impl Drop for Domain {
+    fn drop(&mut self) {
+        for r in take(self.retired.get_mut().unwrap()) {
+            unsafe { (r.drop_fn)(r.addr) };
+        }
+        let mut record = *self.records.get_mut();
+        while !record.is_null() {
+            let r = unsafe { Box::from_raw(record) };
+            record = r.next;
+        }
+    }
+}
+
+unsafe impl Sync for Domain {}
+unsafe impl Send for Domain {}
+
+/// An owned hazard pointer slot, released back to its domain on drop.
+pub(crate) struct HazardPointer<'d> {
+    record: &'d Record,
+}
+
+impl HazardPointer<'_> {
+    /// Loads `src` and publishes it as hazardous, retrying until the published value is current.
+    pub(crate) fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut addr = src.load(Ordering::SeqCst);
+        loop {
+            self.set(addr);
+            let current = src.load(Ordering::SeqCst);
+            if current == addr {
+                return addr;
+            }
+            addr = current;
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn set<T>(&self, addr: *mut T) {
+        self.record.hazard.store(addr.cast(), Ordering::SeqCst);
+    }
+
+    #[inline(always)]
+    pub(crate) fn clear(&self) {
+        self.record.hazard.store(null_mut(), Ordering::Release);
+    }
+}
+
+impl Drop for HazardPointer<'_> {
+    fn drop(&mut self) {
+        self.clear();
+        self.record.active.store(false, Ordering::Release);
+    }
+}
+
+pub(crate) unsafe fn drop_box<T>(addr: *mut ()) {
+    drop(Box::from_raw(addr.cast::<T>()));
+}
+
+/// An `AtomicOption` whose replaced values are retired to a hazard pointer `Domain`, so the
+/// current value can be borrowed through `protect` while other threads swap it out.
+pub struct HazardAtomicOption<T> {
+    inner: AtomicPtr<T>,
+    domain: &'static Domain,
+    _phantom: PhantomUnsync<T>,
+}
+
+impl<T> HazardAtomicOption<T> {
+    #[inline(always)]
+    pub fn new(data: Option<Box<T>>) -> HazardAtomicOption<T> {
+        Self::with_domain(data, Domain::global())
+    }
+
+    #[inline(always)]
+    pub fn with_domain(data: Option<Box<T>>, domain: &'static Domain) -> HazardAtomicOption<T> {
+        HazardAtomicOption {
+            inner: AtomicPtr::new(data.map_or(null_mut(), Box::into_raw)),
+            domain,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Borrows the current value, keeping it alive until the guard is dropped.
+    pub fn protect(&self) -> Option<HazardGuard<'_, T>> {
+        let hazard = self.domain.hazard_pointer();
+        let addr = hazard.protect(&self.inner);
+        HazardGuard::new(hazard, addr)
+    }
+}
+
+impl<T: Send + 'static> HazardAtomicOption<T> {
+    /// Replaces the value, returning the old one protected until the guard is dropped.
+    pub fn swap(&self, new: Option<Box<T>>) -> Option<HazardGuard<'_, T>> {
+        let hazard = self.domain.hazard_pointer();
+        let addr = self
+            .inner
+            .swap(new.map_or(null_mut(), Box::into_raw), Ordering::SeqCst);
+        hazard.set(addr);
+        unsafe { self.retire(addr) };
+        HazardGuard::new(hazard, addr)
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<HazardGuard<'_, T>> {
+        self.swap(None)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<Box<T>>) {
+        let addr = self
+            .inner
+            .swap(new.map_or(null_mut(), Box::into_raw), Ordering::SeqCst);
+        unsafe { self.retire(addr) };
+    }
+
+    /// Takes the value only if `pred` approves of it.
+    ///
+    /// The value is protected while `pred` runs, so its address cannot be reused and a successful
+    /// exchange always removes the value `pred` saw.
+    pub fn take_if<F>(&self, mut pred: F) -> Option<HazardGuard<'_, T>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let hazard = self.domain.hazard_pointer();
+        loop {
+            let addr = hazard.protect(&self.inner);
+            if !pred(unsafe { addr.as_ref() }?) {
+                return None;
+            }
+            if self
+                .inner
+                .compare_exchange(addr, null_mut(), Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                unsafe { self.retire(addr) };
+                return HazardGuard::new(hazard, addr);
+            }
+        }
+    }
+
+    /// Replaces the value with the box `f` builds from it, protecting the current value while `f`
+    /// runs and retrying whenever another writer got in first.
+    ///
+    /// After a lost round, `f` gets back the box it built so it can refill that allocation instead
+    /// of making a new one. Returns the replaced value, or `Err` with the value `f` declined to
+    /// replace by returning `None`.
+    #[allow(clippy::type_complexity)]
+    pub fn fetch_update<F>(
+        &self,
+        mut f: F,
+    ) -> Result<Option<HazardGuard<'_, T>>, Option<HazardGuard<'_, T>>>
+    where
+        F: FnMut(Option<&T>, Option<Box<T>>) -> Option<Box<T>>,
+    {
+        let hazard = self.domain.hazard_pointer();
+        let mut rejected = None;
+        loop {
+            let addr = hazard.protect(&self.inner);
+            let Some(new) = f(unsafe { addr.as_ref() }, rejected.take()) else {
+                return Err(HazardGuard::new(hazard, addr));
+            };
+            let new = Box::into_raw(new);
+            if self
+                .inner
+                .compare_exchange(addr, new, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                unsafe { self.retire(addr) };
+                return Ok(HazardGuard::new(hazard, addr));
+            }
+            rejected = Some(unsafe { Box::from_raw(new) });
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn retire(&self, addr: *mut T) {
+        if !addr.is_null() {
+            self.domain.retire(addr.cast(), drop_box::<T>);
+        }
+    }
+}
+
+unsafe impl<T> Sync for HazardAtomicOption<T> where T: Send + Sync {}
+unsafe impl<T> Send for HazardAtomicOption<T> where T: Send {}
+
+impl<T> Drop for HazardAtomicOption<T> {
+    fn drop(&mut self) {
+        let addr = *self.inner.get_mut();
+        if !addr.is_null() {
+            drop(unsafe { Box::from_raw(addr) });
+        }
+    }
+}
+
+/// A borrowed value kept alive by a hazard pointer.
+pub struct HazardGuard<'a, T> {
+    _hazard: HazardPointer<'a>,
+    addr: NonNull<T>,
+    _phantom: PhantomData<&'a T>,
+}
+
+impl<'a, T> HazardGuard<'a, T> {
+    #[inline(always)]
+    fn new(hazard: HazardPointer<'a>, addr: *mut T) -> Option<HazardGuard<'a, T>> {
+        Some(HazardGuard {
+            _hazard: hazard,
+            addr: NonNull::new(addr)?,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<T> Deref for HazardGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { self.addr.as_ref() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::{Domain, HazardAtomicOption, RETIRE_THRESHOLD};
+
+    struct Counted(u64, &'static AtomicUsize);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_simple() {
+        static DOMAIN: Domain = Domain::new();
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        let opt = HazardAtomicOption::with_domain(None, &DOMAIN);
+        assert!(opt.protect().is_none());
+        opt.store(Some(Box::new(Counted(0, &DROPPED))));
+        let guard = opt.protect().unwrap();
+        assert_eq!(guard.0, 0);
+        let old = opt.swap(Some(Box::new(Counted(1, &DROPPED)))).unwrap();
+        assert_eq!(old.0, 0);
+        DOMAIN.reclaim();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+        drop(guard);
+        drop(old);
+        DOMAIN.reclaim();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        assert_eq!(opt.take().unwrap().0, 1);
+        assert!(opt.protect().is_none());
+        DOMAIN.reclaim();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_take_if() {
+        let opt = HazardAtomicOption::new(Some(Box::new(0)));
+        assert!(opt.take_if(|v| *v > 0).is_none());
+        assert_eq!(*opt.protect().unwrap(), 0);
+        assert_eq!(*opt.take_if(|v| *v == 0).unwrap(), 0);
+        assert!(opt.take_if(|_| unreachable!()).is_none());
+    }
+
+    #[test]
+    fn test_fetch_update() {
+        let opt = HazardAtomicOption::new(None);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let res = opt.fetch_update(|v, rejected| {
+                            let mut data = rejected.unwrap_or_default();
+                            *data = v.map_or(1, |v| v + 1);
+                            Some(data)
+                        });
+                        assert!(res.is_ok());
+                    }
+                });
+            }
+        });
+        let res = opt.fetch_update(|_, _| None);
+        assert_eq!(res.err().flatten().as_deref(), Some(&400));
+    }
+
+    #[test]
+    fn test_fetch_update_reuse() {
+        let opt = HazardAtomicOption::new(Some(Box::new(0)));
+        let mut lost = None;
+        let res = opt.fetch_update(|v, rejected| {
+            let next = v.unwrap() + 1;
+            let Some(mut data) = rejected else {
+                // Get in first, so this round loses.
+                opt.store(Some(Box::new(10)));
+                let data = Box::new(next);
+                lost = Some(&*data as *const i32);
+                return Some(data);
+            };
+            assert_eq!(Some(&*data as *const i32), lost);
+            *data = next;
+            Some(data)
+        });
+        assert_eq!(res.ok().flatten().as_deref(), Some(&10));
+        assert_eq!(opt.protect().as_deref(), Some(&11));
+    }
+
+    #[test]
+    fn test_bounded_garbage() {
+        static DOMAIN: Domain = Domain::new();
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        let opt = HazardAtomicOption::with_domain(Some(Box::new(Counted(0, &DROPPED))), &DOMAIN);
+        let stop = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        let v = opt.protect().unwrap();
+                        assert!(v.0 >= last);
+                        last = v.0;
+                    }
+                });
+            }
+            for i in 1..1000 {
+                opt.store(Some(Box::new(Counted(i, &DROPPED))));
+                let pending = i as usize - DROPPED.load(Ordering::Relaxed);
+                assert!(pending <= RETIRE_THRESHOLD + 4);
+            }
+            stop.store(true, Ordering::Relaxed);
+        });
+        DOMAIN.reclaim();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 999);
+    }
+}
@@ -0,0 +1,311 @@
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::marker::PhantomData;
+use core::mem::{replace, size_of, MaybeUninit};
+use core::ptr;
+
+use crate::atomic::{fence, AtomicUsize, Ordering};
+
+/// An atomic `Option<T>` that keeps small `Copy` values inline instead of boxing them.
+///
+/// Writers serialize on a sequence word that is odd while one of them is writing. `load` reads
+/// optimistically and retries if a writer interfered, so readers never block each other. Types
+/// without padding can use the lock-free `AtomicOptionPacked` instead.
+pub struct AtomicOptionInline<T: Copy> {
+    seq: AtomicUsize,
+    value: UnsafeCell<Option<T>>,
+}
+
+impl<T: Copy> AtomicOptionInline<T> {
+    #[inline(always)]
+    pub const fn new(data: Option<T>) -> AtomicOptionInline<T> {
+        AtomicOptionInline {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn swap(&self, new: Option<T>) -> Option<T> {
+        let seq = self.lock();
+        let old = replace(unsafe { &mut *self.value.get() }, new);
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+        old
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<T> {
+        self.swap(None)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<T>) {
+        self.swap(new);
+    }
+
+    #[inline(always)]
+    pub fn load(&self) -> Option<T> {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                // The read may race with a writer, in which case the copy is discarded unread.
+                // Like crossbeam's `AtomicCell`, this relies on volatile reads of possibly torn
+                // data being harmless in practice.
+                let value = unsafe {
+                    ptr::read_volatile(self.value.get().cast::<MaybeUninit<Option<T>>>())
+                };
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return unsafe { value.assume_init() };
+                }
+            }
+            spin_loop();
+        }
+    }
+
+    /// Makes the sequence word odd, returning its previous value.
+    #[inline(always)]
+    fn lock(&self) -> usize {
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 1 == 0 {
+                match self.seq.compare_exchange_weak(
+                    seq,
+                    seq.wrapping_add(1),
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => seq = current,
+                }
+            } else {
+                spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+            }
+        }
+        // Keeps the writes to the value from becoming visible before the odd sequence word.
+        fence(Ordering::Release);
+        seq
+    }
+}
+
+unsafe impl<T: Copy> Sync for AtomicOptionInline<T> where T: Send {}
+unsafe impl<T: Copy> Send for AtomicOptionInline<T> where T: Send {}
+
+/// Types whose values have no padding or other uninitialized bytes.
+///
+/// # Safety
+///
+/// Every byte of every value of the type must be initialized.
+pub unsafe trait NoUninit: Copy {}
+
+macro_rules! impl_no_uninit {
+    ($($t:ty),*) => {
+        $(unsafe impl NoUninit for $t {})*
+    };
+}
+
+impl_no_uninit!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char
+);
+
+unsafe impl<T: NoUninit, const N: usize> NoUninit for [T; N] {}
+
+/// An atomic `Option<T>` that packs values of padding-free types into a single integer atomic `W`,
+/// together with a byte marking whether a value is present.
+///
+/// `T` must be smaller than `W`: `u64` holds values of up to 7 bytes and `u128`, which needs the
+/// `portable-atomic` feature, up to 15 bytes.
+pub struct AtomicOptionPacked<T: NoUninit, W: Word = u64> {
+    inner: W::Atomic,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: NoUninit, W: Word> AtomicOptionPacked<T, W> {
+    const FITS: () = assert!(
+        size_of::<T>() < size_of::<W>(),
+        "value does not fit the word"
+    );
+
+    #[inline(always)]
+    pub fn new(data: Option<T>) -> AtomicOptionPacked<T, W> {
+        AtomicOptionPacked {
+            inner: W::new(Self::pack(data)),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn swap(&self, new: Option<T>) -> Option<T> {
+        Self::unpack(W::swap(&self.inner, Self::pack(new)))
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<T> {
+        self.swap(None)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<T>) {
+        W::store(&self.inner, Self::pack(new))
+    }
+
+    #[inline(always)]
+    pub fn load(&self) -> Option<T> {
+        Self::unpack(W::load(&self.inner))
+    }
+
+    /// Copies the value's bytes to the start of the word and sets its last byte if there is one.
+    #[inline(always)]
+    fn pack(data: Option<T>) -> W {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS;
+        let mut word = W::default();
+        if let Some(value) = data {
+            let bytes = (&mut word as *mut W).cast::<u8>();
+            unsafe {
+                ptr::copy_nonoverlapping((&value as *const T).cast::<u8>(), bytes, size_of::<T>());
+                bytes.add(size_of::<W>() - 1).write(1);
+            }
+        }
+        word
+    }
+
+    #[inline(always)]
+    fn unpack(word: W) -> Option<T> {
+        let bytes = (&word as *const W).cast::<u8>();
+        unsafe {
+            if bytes.add(size_of::<W>() - 1).read() == 0 {
+                None
+            } else {
+                Some(bytes.cast::<T>().read_unaligned())
+            }
+        }
+    }
+}
+
+unsafe impl<T: NoUninit, W: Word> Sync for AtomicOptionPacked<T, W> where T: Send {}
+unsafe impl<T: NoUninit, W: Word> Send for AtomicOptionPacked<T, W> where T: Send {}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// An integer type with a matching atomic, used as the storage of `AtomicOptionPacked`.
+pub trait Word: sealed::Sealed + Copy + Default {
+    #[doc(hidden)]
+    type Atomic;
+    #[doc(hidden)]
+    fn new(value: Self) -> Self::Atomic;
+    #[doc(hidden)]
+    fn swap(atomic: &Self::Atomic, value: Self) -> Self;
+    #[doc(hidden)]
+    fn store(atomic: &Self::Atomic, value: Self);
+    #[doc(hidden)]
+    fn load(atomic: &Self::Atomic) -> Self;
+}
+
+macro_rules! impl_word {
+    ($t:ty, $atomic:ty) => {
+        impl sealed::Sealed for $t {}
+
+        impl Word for $t {
+            type Atomic = $atomic;
+
+            #[inline(always)]
+            fn new(value: $t) -> $atomic {
+                <$atomic>::new(value)
+            }
+
+            #[inline(always)]
+            fn swap(atomic: &$atomic, value: $t) -> $t {
+                atomic.swap(value, Ordering::AcqRel)
+            }
+
+            #[inline(always)]
+            fn store(atomic: &$atomic, value: $t) {
+                atomic.store(value, Ordering::Release)
+            }
+
+            #[inline(always)]
+            fn load(atomic: &$atomic) -> $t {
+                atomic.load(Ordering::Acquire)
+            }
+        }
+    };
+}
+
+#[cfg(any(feature = "portable-atomic", target_has_atomic = "64"))]
+impl_word!(u64, crate::atomic::AtomicU64);
+#[cfg(feature = "portable-atomic")]
+impl_word!(u128, portable_atomic::AtomicU128);
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{AtomicOptionInline, AtomicOptionPacked};
+
+    #[test]
+    fn test_simple() {
+        let opt = AtomicOptionInline::new(None);
+        assert_eq!(opt.take(), None);
+        assert_eq!(opt.swap(Some(0u32)), None);
+        assert_eq!(opt.load(), Some(0));
+        assert_eq!(opt.take(), Some(0));
+        opt.store(Some(1));
+        opt.store(Some(2));
+        assert_eq!(opt.swap(Some(3)), Some(2));
+    }
+
+    #[test]
+    fn test_packed() {
+        let opt = AtomicOptionPacked::<[u16; 3]>::new(None);
+        assert_eq!(opt.take(), None);
+        assert_eq!(opt.swap(Some([0, 1, 2])), None);
+        assert_eq!(opt.load(), Some([0, 1, 2]));
+        opt.store(Some([0; 3]));
+        assert_eq!(opt.take(), Some([0; 3]));
+        assert_eq!(opt.load(), None);
+
+        #[cfg(feature = "portable-atomic")]
+        {
+            let opt = AtomicOptionPacked::<u64, u128>::new(Some(u64::MAX));
+            assert_eq!(opt.swap(Some(0)), Some(u64::MAX));
+            assert_eq!(opt.take(), Some(0));
+            assert_eq!(opt.take(), None);
+        }
+    }
+
+    #[test]
+    fn test_two_threads() {
+        let opt = AtomicOptionInline::<[u64; 4]>::new(None);
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut remain = 1000;
+                while remain > 0 {
+                    if let Some(v) = opt.take() {
+                        assert!(v.iter().all(|x| *x == v[0]));
+                        remain -= 1;
+                    }
+                    thread::yield_now();
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..10000 {
+                    if let Some(v) = opt.load() {
+                        assert!(v.iter().all(|x| *x == v[0]));
+                    }
+                }
+            });
+            let mut remain = 1000;
+            while remain > 0 {
+                if opt.load().is_none() {
+                    opt.store(Some([remain; 4]));
+                    remain -= 1;
+                }
+                thread::yield_now();
+            }
+        });
+    }
+}
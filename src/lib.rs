@@ -1,19 +1,88 @@
-use std::marker::PhantomData;
-use std::ptr::null_mut;
-use std::sync::atomic::{AtomicPtr, Ordering};
+#![no_std]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+use alloc::boxed::Box;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{align_of, replace, size_of};
+use core::ptr::{null, null_mut};
+
+use crate::allocator::{BoxAllocator, Global};
+use crate::atomic::{AtomicPtr, Ordering};
+
+pub mod allocator;
+#[cfg(feature = "std")]
+pub mod arc;
+mod atomic;
+#[cfg(feature = "std")]
+pub mod blocking;
+pub mod boxed;
+#[cfg(feature = "epoch")]
+pub mod epoch;
+#[cfg(any(
+    target_pointer_width = "64",
+    all(
+        target_pointer_width = "32",
+        any(feature = "portable-atomic", target_has_atomic = "64")
+    )
+))]
+pub mod generation;
+#[cfg(feature = "std")]
+pub mod hazard;
+pub mod inline;
+pub mod mailbox;
+pub mod once;
+#[cfg(feature = "std")]
+pub mod oneshot;
+#[cfg(feature = "std")]
+mod park;
+#[cfg(any(feature = "portable-atomic", target_has_atomic = "64"))]
+pub mod pool;
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(feature = "std")]
+pub mod stack;
+pub mod tagged;
+pub mod triple_buffer;
 
 type PhantomUnsync<T> = PhantomData<*mut T>;
 
-pub struct AtomicOption<T> {
+// `repr(C)` keeps `inner` at offset 0, which `from_mut` relies on.
+#[repr(C)]
+pub struct AtomicOption<T, A: BoxAllocator = Global> {
     inner: AtomicPtr<T>,
+    alloc: A,
     _phantom: PhantomUnsync<T>,
 }
 
 impl<T> AtomicOption<T> {
     #[inline(always)]
     pub fn new(data: Option<Box<T>>) -> AtomicOption<T> {
+        Self::new_in(data, Global)
+    }
+
+    /// Views an exclusively borrowed `Option<Box<T>>` as an `AtomicOption`.
+    #[inline(always)]
+    pub fn from_mut(data: &mut Option<Box<T>>) -> &mut AtomicOption<T> {
+        const {
+            assert!(align_of::<AtomicPtr<T>>() == align_of::<Option<Box<T>>>());
+            assert!(size_of::<AtomicOption<T>>() == size_of::<Option<Box<T>>>());
+        }
+        unsafe { &mut *(data as *mut Option<Box<T>>).cast() }
+    }
+}
+
+impl<T, A: BoxAllocator> AtomicOption<T, A> {
+    /// Creates a slot whose values are freed through `alloc`.
+    #[inline(always)]
+    pub fn new_in(data: Option<A::Box<T>>, alloc: A) -> AtomicOption<T, A> {
         let empty = AtomicOption {
             inner: AtomicPtr::new(null_mut()),
+            alloc,
             _phantom: PhantomData,
         };
         empty.store(data);
@@ -21,44 +90,282 @@ impl<T> AtomicOption<T> {
     }
 
     #[inline(always)]
-    pub fn swap(&self, new: Option<Box<T>>) -> Option<Box<T>> {
-        let addr = if let Some(new) = new {
-            Box::into_raw(new)
+    pub fn swap(&self, new: Option<A::Box<T>>) -> Option<A::Box<T>> {
+        self.swap_with_ordering(new, Ordering::AcqRel)
+    }
+
+    /// Like `swap`, but with a caller-selected ordering.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `order` is `AcqRel` or `SeqCst`, or `new` is `None` and `order` is `Acquire`.
+    #[inline(always)]
+    pub fn swap_with_ordering(&self, new: Option<A::Box<T>>, order: Ordering) -> Option<A::Box<T>> {
+        check_ordering(order, new.is_some());
+        let new = Self::into_raw(new);
+        let addr = self.inner.swap(new, order);
+        unsafe { self.rebox(addr) }
+    }
+
+    /// Returns the address currently held by the slot, or null if it is empty.
+    ///
+    /// The pointer is only an identity token for `compare_exchange` and must not be dereferenced.
+    #[inline(always)]
+    pub fn load_ptr(&self) -> *const T {
+        self.inner.load(Ordering::Acquire)
+    }
+
+    /// Whether the slot held a value at the time of the call.
+    #[inline(always)]
+    pub fn is_some(&self) -> bool {
+        !self.load_ptr().is_null()
+    }
+
+    #[inline(always)]
+    pub fn is_none(&self) -> bool {
+        self.load_ptr().is_null()
+    }
+
+    /// Stores `new` if the slot still holds `current` (null for `None`).
+    ///
+    /// On success the previous value is returned, on failure `new` is handed back untouched.
+    #[allow(clippy::type_complexity)]
+    #[inline(always)]
+    pub fn compare_exchange(
+        &self,
+        current: *const T,
+        new: Option<A::Box<T>>,
+    ) -> Result<Option<A::Box<T>>, Option<A::Box<T>>> {
+        let new = Self::into_raw(new);
+        match self.inner.compare_exchange(
+            current as *mut T,
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(old) => Ok(unsafe { self.rebox(old) }),
+            Err(_) => Err(unsafe { self.rebox(new) }),
+        }
+    }
+
+    /// Like `compare_exchange`, but may fail spuriously.
+    #[allow(clippy::type_complexity)]
+    #[inline(always)]
+    pub fn compare_exchange_weak(
+        &self,
+        current: *const T,
+        new: Option<A::Box<T>>,
+    ) -> Result<Option<A::Box<T>>, Option<A::Box<T>>> {
+        let new = Self::into_raw(new);
+        match self.inner.compare_exchange_weak(
+            current as *mut T,
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(old) => Ok(unsafe { self.rebox(old) }),
+            Err(_) => Err(unsafe { self.rebox(new) }),
+        }
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<A::Box<T>> {
+        self.swap(None)
+    }
+
+    /// Like `take`, but with a caller-selected ordering.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `order` is `Acquire`, `AcqRel` or `SeqCst`.
+    #[inline(always)]
+    pub fn take_with_ordering(&self, order: Ordering) -> Option<A::Box<T>> {
+        self.swap_with_ordering(None, order)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<A::Box<T>>) {
+        drop(self.swap(new))
+    }
+
+    /// Like `store`, but with a caller-selected ordering.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `order` is `AcqRel` or `SeqCst`, or `new` is `None` and `order` is `Acquire`.
+    #[inline(always)]
+    pub fn store_with_ordering(&self, new: Option<A::Box<T>>, order: Ordering) {
+        drop(self.swap_with_ordering(new, order))
+    }
+
+    /// Borrows the value through an exclusive reference, without any atomic operation.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.inner.get_mut().as_mut() }
+    }
+
+    /// Like `swap`, but through an exclusive reference, without any atomic operation.
+    #[inline(always)]
+    pub fn replace_mut(&mut self, new: Option<A::Box<T>>) -> Option<A::Box<T>> {
+        let addr = replace(self.inner.get_mut(), Self::into_raw(new));
+        unsafe { self.rebox(addr) }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(mut self) -> Option<A::Box<T>> {
+        self.replace_mut(None)
+    }
+
+    /// Stores `new` only if the slot is empty, otherwise hands it back.
+    #[inline(always)]
+    pub fn try_store(&self, new: A::Box<T>) -> Result<(), A::Box<T>> {
+        match self.compare_exchange(null(), Some(new)) {
+            Ok(_) => Ok(()),
+            Err(new) => Err(new.unwrap()),
+        }
+    }
+
+    /// Like `try_store`, but only calls `f` if the slot looked empty.
+    ///
+    /// Returns `Err(None)` if `f` was never called, or `Err(Some(_))` with the freshly built value
+    /// if another writer filled the slot in the meantime.
+    #[inline(always)]
+    pub fn try_store_with<F>(&self, f: F) -> Result<(), Option<A::Box<T>>>
+    where
+        F: FnOnce() -> A::Box<T>,
+    {
+        if !self.load_ptr().is_null() {
+            return Err(None);
+        }
+        self.try_store(f()).map_err(Some)
+    }
+
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    fn key(&self) -> usize {
+        &self.inner as *const AtomicPtr<T> as usize
+    }
+
+    #[inline(always)]
+    fn into_raw(data: Option<A::Box<T>>) -> *mut T {
+        if let Some(data) = data {
+            A::into_raw(data).0
         } else {
             null_mut()
-        };
+        }
+    }
 
-        let addr = self.inner.swap(addr, Ordering::AcqRel);
+    #[inline(always)]
+    unsafe fn rebox(&self, addr: *mut T) -> Option<A::Box<T>> {
         if addr.is_null() {
             None
         } else {
-            Some(unsafe { Box::from_raw(addr) })
+            Some(A::from_raw(addr, self.alloc.clone()))
         }
     }
+}
+
+/// Checks that `order` can soundly move a box out of the slot and, if `publish`, one into it.
+#[inline(always)]
+fn check_ordering(order: Ordering, publish: bool) {
+    match order {
+        Ordering::AcqRel | Ordering::SeqCst => {}
+        Ordering::Acquire if !publish => {}
+        Ordering::Acquire => panic!("there is no such thing as an acquire-only publishing swap"),
+        _ => panic!("taking ownership of the old value requires an acquire ordering"),
+    }
+}
+
+unsafe impl<T, A> Sync for AtomicOption<T, A>
+where
+    T: Send,
+    A: BoxAllocator + Send + Sync,
+{
+}
+unsafe impl<T, A> Send for AtomicOption<T, A>
+where
+    T: Send,
+    A: BoxAllocator + Send,
+{
+}
 
+impl<T, A: BoxAllocator + Default> Default for AtomicOption<T, A> {
     #[inline(always)]
-    pub fn take(&self) -> Option<Box<T>> {
-        self.swap(None)
+    fn default() -> AtomicOption<T, A> {
+        AtomicOption::new_in(None, A::default())
     }
+}
 
+impl<T> From<Option<Box<T>>> for AtomicOption<T> {
     #[inline(always)]
-    pub fn store(&self, new: Option<Box<T>>) {
-        drop(self.swap(new))
+    fn from(data: Option<Box<T>>) -> AtomicOption<T> {
+        AtomicOption::new(data)
     }
 }
 
-unsafe impl<T> Sync for AtomicOption<T> where T: Send {}
-unsafe impl<T> Send for AtomicOption<T> where T: Send {}
+impl<T> From<Box<T>> for AtomicOption<T> {
+    #[inline(always)]
+    fn from(data: Box<T>) -> AtomicOption<T> {
+        AtomicOption::new(Some(data))
+    }
+}
 
-impl<T> Drop for AtomicOption<T> {
+impl<T> From<T> for AtomicOption<T> {
+    #[inline(always)]
+    fn from(data: T) -> AtomicOption<T> {
+        AtomicOption::new(Some(Box::new(data)))
+    }
+}
+
+/// Stores each item in turn, so the slot ends up holding the last one, or nothing if there were
+/// none.
+impl<T> FromIterator<Box<T>> for AtomicOption<T> {
+    #[inline(always)]
+    fn from_iter<I: IntoIterator<Item = Box<T>>>(iter: I) -> AtomicOption<T> {
+        AtomicOption::new(iter.into_iter().last())
+    }
+}
+
+/// Boxes the last item, like collecting `Box<T>`.
+impl<T> FromIterator<T> for AtomicOption<T> {
+    #[inline(always)]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> AtomicOption<T> {
+        AtomicOption::new(iter.into_iter().last().map(Box::new))
+    }
+}
+
+/// Only reports whether a value is present, since the value itself cannot be borrowed.
+impl<T, A: BoxAllocator> fmt::Debug for AtomicOption<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.is_some() {
+            format_args!("Some(..)")
+        } else {
+            format_args!("None")
+        };
+        f.debug_tuple("AtomicOption").field(&state).finish()
+    }
+}
+
+impl<T, A: BoxAllocator> Drop for AtomicOption<T, A> {
     fn drop(&mut self) {
-        let _ = self.take();
+        drop(self.replace_mut(None));
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{mem::transmute, thread};
+    use std::boxed::Box;
+    #[cfg(feature = "std")]
+    use std::future::Future;
+    #[cfg(feature = "std")]
+    use std::pin::pin;
+    #[cfg(feature = "std")]
+    use std::sync::Arc;
+    #[cfg(feature = "std")]
+    use std::task::{Context, Poll, Wake};
+    #[cfg(feature = "std")]
+    use std::thread::Thread;
+    use std::{mem::transmute, ptr::null, sync::atomic::Ordering, thread};
 
     use super::AtomicOption;
 
@@ -73,6 +380,119 @@ mod tests {
         assert_eq!(opt.swap(Some(Box::new(3))), Some(Box::new(2)));
     }
 
+    #[test]
+    fn test_compare_exchange() {
+        let opt = AtomicOption::new(None);
+        assert_eq!(opt.compare_exchange(null(), Some(Box::new(0))), Ok(None));
+        let current = opt.load_ptr();
+        assert_eq!(
+            opt.compare_exchange(null(), Some(Box::new(1))),
+            Err(Some(Box::new(1)))
+        );
+        assert_eq!(opt.compare_exchange(current, None), Ok(Some(Box::new(0))));
+        assert!(opt.load_ptr().is_null());
+        let mut res = opt.compare_exchange_weak(null(), Some(Box::new(2)));
+        while let Err(new) = res {
+            res = opt.compare_exchange_weak(null(), new);
+        }
+        assert_eq!(opt.take(), Some(Box::new(2)));
+    }
+
+    #[test]
+    fn test_try_store() {
+        let opt = AtomicOption::new(None);
+        assert_eq!(opt.try_store(Box::new(0)), Ok(()));
+        assert_eq!(opt.try_store(Box::new(1)), Err(Box::new(1)));
+        assert_eq!(opt.try_store_with(|| unreachable!()), Err(None));
+        assert_eq!(opt.take(), Some(Box::new(0)));
+        assert_eq!(opt.try_store_with(|| Box::new(2)), Ok(()));
+        assert_eq!(opt.take(), Some(Box::new(2)));
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let mut opt = AtomicOption::new(Some(Box::new(0)));
+        *opt.get_mut().unwrap() += 1;
+        assert_eq!(opt.replace_mut(None), Some(Box::new(1)));
+        assert_eq!(opt.get_mut(), None);
+        opt.store(Some(Box::new(2)));
+        assert_eq!(opt.into_inner(), Some(Box::new(2)));
+
+        let mut data = Some(Box::new(3));
+        let opt = AtomicOption::from_mut(&mut data);
+        assert_eq!(opt.swap(Some(Box::new(4))), Some(Box::new(3)));
+        assert_eq!(data, Some(Box::new(4)));
+    }
+
+    #[test]
+    fn test_traits() {
+        let opt = AtomicOption::<i32>::default();
+        assert!(opt.is_none());
+        assert_eq!(alloc::format!("{opt:?}"), "AtomicOption(None)");
+        let opt = AtomicOption::<i32>::from(0);
+        assert!(opt.is_some());
+        assert_eq!(alloc::format!("{opt:?}"), "AtomicOption(Some(..))");
+        assert_eq!(
+            AtomicOption::<i32>::from(Box::new(1)).take(),
+            Some(Box::new(1))
+        );
+        assert_eq!(
+            AtomicOption::<i32>::from(Some(Box::new(2))).take(),
+            Some(Box::new(2))
+        );
+        let opt: AtomicOption<i32> = (0..3).collect();
+        assert_eq!(opt.take(), Some(Box::new(2)));
+        let opt: AtomicOption<i32> = (0..3).map(Box::new).collect();
+        assert_eq!(opt.take(), Some(Box::new(2)));
+        let opt: AtomicOption<i32> = core::iter::empty::<i32>().collect();
+        assert!(opt.is_none());
+    }
+
+    #[test]
+    fn test_orderings() {
+        let opt = AtomicOption::new(None);
+        opt.store_with_ordering(Some(Box::new(0)), Ordering::SeqCst);
+        assert_eq!(
+            opt.swap_with_ordering(Some(Box::new(1)), Ordering::AcqRel),
+            Some(Box::new(0))
+        );
+        assert_eq!(opt.take_with_ordering(Ordering::Acquire), Some(Box::new(1)));
+        opt.store_with_ordering(None, Ordering::Acquire);
+    }
+
+    #[test]
+    #[should_panic(expected = "taking ownership of the old value requires an acquire ordering")]
+    fn test_relaxed_take() {
+        AtomicOption::<i64>::new(None).take_with_ordering(Ordering::Relaxed);
+    }
+
+    #[test]
+    #[should_panic(expected = "there is no such thing as an acquire-only publishing swap")]
+    fn test_acquire_store() {
+        AtomicOption::new(None).store_with_ordering(Some(Box::new(0)), Ordering::Acquire);
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+        struct ThreadWaker(Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let mut fut = pin!(fut);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+                return res;
+            }
+            thread::park();
+        }
+    }
+
     #[test]
     fn test_two_threads() {
         for _ in 0..100 {
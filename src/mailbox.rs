@@ -0,0 +1,247 @@
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use core::future::Future;
+#[cfg(feature = "std")]
+use core::pin::Pin;
+#[cfg(feature = "std")]
+use core::task::{ready, Context, Poll};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use crate::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use crate::blocking::{BlockingAtomicOption, Take};
+#[cfg(not(feature = "std"))]
+use crate::AtomicOption;
+
+#[cfg(feature = "std")]
+type Slot<T> = BlockingAtomicOption<T>;
+#[cfg(not(feature = "std"))]
+type Slot<T> = AtomicOption<T>;
+
+/// A slot holding only the latest posted value, where each post overwrites any unread older one.
+///
+/// Concurrent producers may reach the slot out of version order. A post never replaces a newer
+/// letter, and a letter older than one already received is discarded, so every consumer sees
+/// versions in strictly increasing order.
+pub struct Mailbox<T> {
+    slot: Slot<Letter<T>>,
+    version: AtomicUsize,
+    delivered: AtomicUsize,
+}
+
+/// A value taken from a `Mailbox` together with the version it was posted as.
+///
+/// Versions start at 1 and grow by one per post. A consumer that receives version `w` after `v`
+/// missed `w - v - 1` updates.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Letter<T> {
+    pub version: usize,
+    pub value: Box<T>,
+}
+
+impl<T> Mailbox<T> {
+    #[inline(always)]
+    pub fn new() -> Mailbox<T> {
+        Mailbox {
+            slot: Slot::new(None),
+            version: AtomicUsize::new(0),
+            delivered: AtomicUsize::new(0),
+        }
+    }
+
+    /// Posts `value`, returning the older value that is now stale if nobody received it.
+    ///
+    /// If a concurrent post with a newer version got there first, that one is put back and `value`
+    /// itself is returned as stale.
+    pub fn post(&self, value: Box<T>) -> Option<Box<T>> {
+        let version = self.version.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut hand = Box::new(Letter { version, value });
+        loop {
+            let version = hand.version;
+            match self.slot.swap(Some(hand)) {
+                None => return None,
+                Some(prev) if prev.version < version => return Some(prev.value),
+                Some(prev) => hand = prev,
+            }
+        }
+    }
+
+    /// The version of the most recent post, or 0 if nothing was posted yet.
+    #[inline(always)]
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    pub fn try_recv(&self) -> Option<Letter<T>> {
+        loop {
+            if let Some(letter) = self.accept(*self.slot.take()?) {
+                return Some(letter);
+            }
+        }
+    }
+
+    /// Parks the current thread until a value is posted.
+    #[cfg(feature = "std")]
+    pub fn recv(&self) -> Letter<T> {
+        loop {
+            if let Some(letter) = self.accept(*self.slot.take_blocking()) {
+                return letter;
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Letter<T>> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            let remaining = deadline.map_or(timeout, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+            if let Some(letter) = self.accept(*self.slot.take_timeout(remaining)?) {
+                return Some(letter);
+            }
+        }
+    }
+
+    /// Returns a future that resolves once a value is posted, without losing it if dropped early.
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    pub fn recv_async(&self) -> Recv<'_, T> {
+        Recv {
+            mailbox: self,
+            take: self.slot.take_async(),
+        }
+    }
+
+    /// Unwraps `letter` unless a newer one was already received.
+    #[inline(always)]
+    fn accept(&self, letter: Letter<T>) -> Option<Letter<T>> {
+        let newest = self.delivered.fetch_max(letter.version, Ordering::Relaxed);
+        (newest < letter.version).then_some(letter)
+    }
+}
+
+impl<T> Default for Mailbox<T> {
+    fn default() -> Mailbox<T> {
+        Mailbox::new()
+    }
+}
+
+/// Future returned by `Mailbox::recv_async`.
+#[cfg(feature = "std")]
+pub struct Recv<'a, T> {
+    mailbox: &'a Mailbox<T>,
+    take: Take<'a, Letter<T>>,
+}
+
+#[cfg(feature = "std")]
+impl<T> Future for Recv<'_, T> {
+    type Output = Letter<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Letter<T>> {
+        loop {
+            let letter = ready!(Pin::new(&mut self.take).poll(cx));
+            if let Some(letter) = self.mailbox.accept(*letter) {
+                return Poll::Ready(letter);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    #[cfg(feature = "std")]
+    use std::thread;
+
+    use super::{Letter, Mailbox};
+    #[cfg(feature = "std")]
+    use crate::tests::block_on;
+
+    #[test]
+    fn test_overwrite() {
+        let mailbox = Mailbox::new();
+        assert_eq!(mailbox.try_recv(), None);
+        assert_eq!(mailbox.post(Box::new(0)), None);
+        assert_eq!(mailbox.post(Box::new(1)), Some(Box::new(0)));
+        assert_eq!(mailbox.version(), 2);
+        assert_eq!(
+            mailbox.try_recv(),
+            Some(Letter {
+                version: 2,
+                value: Box::new(1)
+            })
+        );
+        assert_eq!(mailbox.try_recv(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_recv() {
+        let mailbox = Mailbox::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut last = 0;
+                while last < 100 {
+                    let letter = if last % 2 == 0 {
+                        mailbox.recv()
+                    } else {
+                        block_on(mailbox.recv_async())
+                    };
+                    assert!(letter.version > last);
+                    assert_eq!(*letter.value, letter.version);
+                    last = letter.version;
+                }
+            });
+            for i in 1..=100 {
+                mailbox.post(Box::new(i));
+            }
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_producers() {
+        use std::collections::HashSet;
+        use std::vec::Vec;
+
+        let mailbox = Mailbox::new();
+        let (stale, received) = thread::scope(|s| {
+            let producers: Vec<_> = (0..4)
+                .map(|p| {
+                    let mailbox = &mailbox;
+                    s.spawn(move || {
+                        (0..1000)
+                            .filter_map(|i| mailbox.post(Box::new(p * 1000 + i)))
+                            .map(|value| *value)
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            let consumer = s.spawn(|| {
+                let mut received = Vec::new();
+                let mut last = 0;
+                while last < 4000 {
+                    if let Some(letter) = mailbox.try_recv() {
+                        assert!(letter.version > last);
+                        last = letter.version;
+                        received.push(*letter.value);
+                    }
+                }
+                received
+            });
+            let stale: Vec<_> = producers
+                .into_iter()
+                .flat_map(|producer| producer.join().unwrap())
+                .collect();
+            (stale, consumer.join().unwrap())
+        });
+        let mut seen = HashSet::new();
+        assert!(stale
+            .iter()
+            .chain(&received)
+            .all(|value| seen.insert(*value)));
+    }
+}
@@ -0,0 +1,114 @@
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+
+use crate::{AtomicOption, PhantomUnsync};
+
+/// A slot that can be written only once, so references to its value stay valid for as long as
+/// the `OnceBox` itself.
+pub struct OnceBox<T> {
+    inner: AtomicOption<T>,
+    _phantom: PhantomUnsync<T>,
+}
+
+impl<T> OnceBox<T> {
+    #[inline(always)]
+    pub fn new() -> OnceBox<T> {
+        OnceBox {
+            inner: AtomicOption::new(None),
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> Option<&T> {
+        unsafe { self.inner.load_ptr().as_ref() }
+    }
+
+    /// Initializes the slot with `value`, handing it back if the slot was already initialized.
+    #[inline(always)]
+    pub fn set(&self, value: Box<T>) -> Result<(), Box<T>> {
+        self.inner.try_store(value)
+    }
+
+    /// Returns the value, initializing it with `f` if the slot is empty.
+    ///
+    /// Racing initializers may each call `f`, but only the first value stored is kept and
+    /// returned to all of them.
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Box<T>,
+    {
+        match self.get_or_try_init(|| Ok::<_, ()>(f())) {
+            Ok(value) => value,
+            Err(()) => unreachable!(),
+        }
+    }
+
+    /// Like `get_or_init`, but leaves the slot empty if `f` fails.
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<Box<T>, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let _ = self.set(f()?);
+        Ok(self.get().unwrap())
+    }
+}
+
+impl<T> Default for OnceBox<T> {
+    fn default() -> OnceBox<T> {
+        OnceBox::new()
+    }
+}
+
+unsafe impl<T> Sync for OnceBox<T> where T: Send + Sync {}
+unsafe impl<T> Send for OnceBox<T> where T: Send {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    #[cfg(feature = "std")]
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    #[cfg(feature = "std")]
+    use std::{ptr, thread, vec::Vec};
+
+    use super::OnceBox;
+
+    #[test]
+    fn test_simple() {
+        let once = OnceBox::new();
+        assert_eq!(once.get(), None);
+        assert_eq!(once.get_or_try_init(|| Err(())), Err(()));
+        assert_eq!(once.get(), None);
+        assert_eq!(*once.get_or_init(|| Box::new(0)), 0);
+        assert_eq!(*once.get_or_init(|| unreachable!()), 0);
+        assert_eq!(once.set(Box::new(1)), Err(Box::new(1)));
+        assert_eq!(once.get_or_try_init(|| Err::<_, ()>(())), Ok(&0));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_race() {
+        let once = OnceBox::new();
+        let calls = AtomicUsize::new(0);
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|i| {
+                    let (once, calls) = (&once, &calls);
+                    s.spawn(move || {
+                        once.get_or_init(|| {
+                            calls.fetch_add(1, Ordering::Relaxed);
+                            Box::new(i)
+                        })
+                    })
+                })
+                .collect();
+            for h in handles {
+                assert!(ptr::eq(h.join().unwrap(), once.get().unwrap()));
+            }
+        });
+        assert!(calls.load(Ordering::Relaxed) >= 1);
+    }
+}
@@ -0,0 +1,222 @@
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::boxed::Box;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::atomic::{AtomicUsize, Ordering};
+use crate::{park, AtomicOption};
+
+const SENDER_DROPPED: usize = 1;
+const RECEIVER_DROPPED: usize = 2;
+
+/// Creates a channel carrying a single value.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        slot: AtomicOption::new(None),
+        state: AtomicUsize::new(0),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver {
+            inner,
+            registration: None,
+        },
+    )
+}
+
+struct Inner<T> {
+    slot: AtomicOption<T>,
+    state: AtomicUsize,
+}
+
+impl<T> Inner<T> {
+    /// Takes the value, or reports a disconnect once the sender is gone and nothing is left.
+    ///
+    /// The state is read with an RMW so that it pairs with the RMW in `Sender::drop` the same way
+    /// `take` pairs with `swap`, which is what lets a parked receiver rely on being notified.
+    fn poll(&self) -> Option<Result<T, RecvError>> {
+        if let Some(data) = self.slot.take() {
+            return Some(Ok(*data));
+        }
+        if self.state.fetch_or(0, Ordering::AcqRel) & SENDER_DROPPED == 0 {
+            return None;
+        }
+        Some(self.slot.take().map(|data| *data).ok_or(RecvError))
+    }
+}
+
+/// The sending half of a `channel`.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `value`, handing it back if the receiver has been dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        if self.is_closed() {
+            return Err(value);
+        }
+        // The receiver is woken by `drop`, which runs as soon as this returns.
+        self.inner.slot.store(Some(Box::new(value)));
+        if self.is_closed() {
+            if let Some(data) = self.inner.slot.take() {
+                return Err(*data);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn is_closed(&self) -> bool {
+        self.inner.state.load(Ordering::Acquire) & RECEIVER_DROPPED != 0
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.inner.state.fetch_or(SENDER_DROPPED, Ordering::AcqRel);
+        park::notify(self.inner.slot.key());
+    }
+}
+
+/// The receiving half of a `channel`, which can also be awaited.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+    registration: Option<usize>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        match self.inner.poll() {
+            Some(res) => res.map_err(|_| TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Parks the current thread until the value arrives or the sender is dropped.
+    pub fn recv(self) -> Result<T, RecvError> {
+        park::wait(self.inner.slot.key(), None, || self.inner.poll()).unwrap()
+    }
+
+    /// Like `recv`, but gives up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now().checked_add(timeout);
+        match park::wait(self.inner.slot.key(), deadline, || self.inner.poll()) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(RecvError)) => Err(RecvTimeoutError::Disconnected),
+            None => Err(RecvTimeoutError::Timeout),
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        let this = self.get_mut();
+        let inner = &this.inner;
+        park::poll_wait(inner.slot.key(), &mut this.registration, cx, || {
+            inner.poll()
+        })
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        park::cancel(self.inner.slot.key(), self.registration.take());
+        self.inner
+            .state
+            .fetch_or(RECEIVER_DROPPED, Ordering::AcqRel);
+    }
+}
+
+/// The sender was dropped without sending a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sender dropped without sending")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("no value sent yet"),
+            TryRecvError::Disconnected => RecvError.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting for the value"),
+            RecvTimeoutError::Disconnected => RecvError.fmt(f),
+        }
+    }
+}
+
+impl Error for RecvError {}
+impl Error for TryRecvError {}
+impl Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{channel, RecvError, RecvTimeoutError, TryRecvError};
+    use crate::tests::block_on;
+
+    #[test]
+    fn test_send_recv() {
+        let (tx, rx) = channel();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        thread::spawn(move || tx.send(0).unwrap());
+        assert_eq!(rx.recv(), Ok(0));
+
+        let (tx, rx) = channel();
+        thread::spawn(move || tx.send(1).unwrap());
+        assert_eq!(block_on(rx), Ok(1));
+    }
+
+    #[test]
+    fn test_dropped() {
+        let (tx, rx) = channel::<i32>();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        thread::spawn(move || drop(tx));
+        assert_eq!(rx.recv(), Err(RecvError));
+
+        let (tx, rx) = channel::<i32>();
+        drop(tx);
+        assert_eq!(block_on(rx), Err(RecvError));
+
+        let (tx, rx) = channel();
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(2), Err(2));
+    }
+}
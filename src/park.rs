@@ -0,0 +1,161 @@
+use core::task::{Context, Poll, Waker};
+use std::sync::Mutex;
+use std::thread::{self, Thread};
+use std::time::Instant;
+use std::vec::Vec;
+
+use crate::atomic::{AtomicUsize, Ordering};
+
+const BUCKET_BITS: u32 = 6;
+
+static BUCKETS: [Bucket; 1 << BUCKET_BITS] = [const { Bucket::new() }; 1 << BUCKET_BITS];
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads and tasks waiting on any key that hashes to this bucket.
+struct Bucket {
+    len: AtomicUsize,
+    queue: Mutex<Vec<Waiter>>,
+}
+
+struct Waiter {
+    key: usize,
+    id: usize,
+    kind: WaiterKind,
+}
+
+enum WaiterKind {
+    Thread(Thread),
+    Task(Waker),
+}
+
+impl WaiterKind {
+    fn wake(self) {
+        match self {
+            WaiterKind::Thread(thread) => thread.unpark(),
+            WaiterKind::Task(waker) => waker.wake(),
+        }
+    }
+}
+
+impl Bucket {
+    const fn new() -> Bucket {
+        Bucket {
+            len: AtomicUsize::new(0),
+            queue: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn register(&self, key: usize, kind: WaiterKind) -> usize {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut queue = self.queue.lock().unwrap();
+        queue.push(Waiter { key, id, kind });
+        self.len.store(queue.len(), Ordering::Relaxed);
+        id
+    }
+
+    fn unregister(&self, id: usize) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.retain(|w| w.id != id);
+        self.len.store(queue.len(), Ordering::Relaxed);
+    }
+}
+
+#[inline(always)]
+fn bucket(key: usize) -> &'static Bucket {
+    let hash = (key as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (u64::BITS - BUCKET_BITS);
+    &BUCKETS[hash as usize]
+}
+
+/// Wakes every thread waiting on `key`.
+///
+/// Waiters register before their final `poll`, so a writer that publishes with a release RMW on
+/// the location the waiter polls with an acquire RMW is guaranteed to see the registration.
+#[inline(always)]
+pub(crate) fn notify(key: usize) {
+    let bucket = bucket(key);
+    if bucket.len.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    let mut woken = Vec::new();
+    {
+        let mut queue = bucket.queue.lock().unwrap();
+        let mut i = 0;
+        while i < queue.len() {
+            if queue[i].key == key {
+                woken.push(queue.swap_remove(i).kind);
+            } else {
+                i += 1;
+            }
+        }
+        bucket.len.store(queue.len(), Ordering::Relaxed);
+    }
+    for kind in woken {
+        kind.wake();
+    }
+}
+
+/// Parks the current thread until `poll` succeeds or `deadline` passes.
+pub(crate) fn wait<R>(
+    key: usize,
+    deadline: Option<Instant>,
+    mut poll: impl FnMut() -> Option<R>,
+) -> Option<R> {
+    let bucket = bucket(key);
+    loop {
+        if let Some(res) = poll() {
+            return Some(res);
+        }
+        let id = bucket.register(key, WaiterKind::Thread(thread::current()));
+        let res = poll();
+        if res.is_none() {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now < deadline {
+                        thread::park_timeout(deadline - now);
+                    }
+                }
+                None => thread::park(),
+            }
+        }
+        bucket.unregister(id);
+        if res.is_some() {
+            return res;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return poll();
+        }
+    }
+}
+
+/// Polls for a result, registering the task's waker on `key` if none is available yet.
+///
+/// `registration` holds the id of the previous registration, which is replaced on every call.
+pub(crate) fn poll_wait<R>(
+    key: usize,
+    registration: &mut Option<usize>,
+    cx: &mut Context<'_>,
+    mut poll: impl FnMut() -> Option<R>,
+) -> Poll<R> {
+    let bucket = bucket(key);
+    if let Some(id) = registration.take() {
+        bucket.unregister(id);
+    }
+    if let Some(res) = poll() {
+        return Poll::Ready(res);
+    }
+    let id = bucket.register(key, WaiterKind::Task(cx.waker().clone()));
+    if let Some(res) = poll() {
+        bucket.unregister(id);
+        return Poll::Ready(res);
+    }
+    *registration = Some(id);
+    Poll::Pending
+}
+
+/// Removes a registration left behind by `poll_wait`.
+pub(crate) fn cancel(key: usize, registration: Option<usize>) {
+    if let Some(id) = registration {
+        bucket(key).unregister(id);
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::boxed::Box;
+use core::mem::MaybeUninit;
+use core::ptr::{self, null_mut};
+
+use crate::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use crate::AtomicOption;
+
+const INDEX_BITS: u32 = 32;
+const INDEX_MASK: u64 = (1 << INDEX_BITS) - 1;
+
+/// A bounded lock-free pool of allocations for `T`.
+///
+/// Spare nodes live in a fixed array of `cap` slots. Slots holding a node and empty slots are
+/// kept on two Treiber stacks of indices, so taking or returning a node is a pop and a push.
+pub struct BoxPool<T> {
+    slots: Box<[Slot<T>]>,
+    full: IndexStack,
+    empty: IndexStack,
+}
+
+struct Slot<T> {
+    node: AtomicPtr<MaybeUninit<T>>,
+    next: AtomicUsize,
+}
+
+impl<T> BoxPool<T> {
+    /// Creates a pool that retains at most `cap` spare nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` does not fit in 32 bits.
+    pub fn new(cap: usize) -> BoxPool<T> {
+        assert!((cap as u64) < INDEX_MASK, "pool capacity too large");
+        let pool = BoxPool {
+            slots: (0..cap)
+                .map(|_| Slot {
+                    node: AtomicPtr::new(null_mut()),
+                    next: AtomicUsize::new(0),
+                })
+                .collect(),
+            full: IndexStack::new(),
+            empty: IndexStack::new(),
+        };
+        for index in 0..cap {
+            pool.empty.push(&pool.slots, index);
+        }
+        pool
+    }
+
+    /// Boxes `value`, reusing a retained node if there is one.
+    #[inline(always)]
+    pub fn boxed(&self, value: T) -> Box<T> {
+        Box::write(self.acquire(), value)
+    }
+
+    /// Moves the value out of `data` and keeps its node for reuse.
+    #[inline(always)]
+    pub fn recycle(&self, data: Box<T>) -> T {
+        let addr = Box::into_raw(data);
+        let value = unsafe { ptr::read(addr) };
+        self.release(unsafe { Box::from_raw(addr.cast::<MaybeUninit<T>>()) });
+        value
+    }
+
+    /// Number of nodes currently retained.
+    ///
+    /// This scans every slot and is only exact while no other thread uses the pool.
+    pub fn retained(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| !slot.node.load(Ordering::Relaxed).is_null())
+            .count()
+    }
+
+    fn acquire(&self) -> Box<MaybeUninit<T>> {
+        let Some(index) = self.full.pop(&self.slots) else {
+            return Box::new_uninit();
+        };
+        let node = self.slots[index].node.swap(null_mut(), Ordering::Relaxed);
+        self.empty.push(&self.slots, index);
+        // Only possible if the generation wrapped while another thread popped the same index.
+        if node.is_null() {
+            return Box::new_uninit();
+        }
+        unsafe { Box::from_raw(node) }
+    }
+
+    fn release(&self, node: Box<MaybeUninit<T>>) {
+        let Some(index) = self.empty.pop(&self.slots) else {
+            return;
+        };
+        self.slots[index]
+            .node
+            .store(Box::into_raw(node), Ordering::Relaxed);
+        self.full.push(&self.slots, index);
+    }
+}
+
+impl<T> Drop for BoxPool<T> {
+    fn drop(&mut self) {
+        for slot in self.slots.iter_mut() {
+            let node = *slot.node.get_mut();
+            if !node.is_null() {
+                drop(unsafe { Box::from_raw(node) });
+            }
+        }
+    }
+}
+
+unsafe impl<T> Sync for BoxPool<T> where T: Send {}
+unsafe impl<T> Send for BoxPool<T> where T: Send {}
+
+/// A Treiber stack of slot indices.
+///
+/// The head holds the top index plus one, or 0 when empty, in its low 32 bits and a generation in
+/// its high 32 bits that every push and pop bumps, so a head that changed and changed back in
+/// between does not compare equal.
+struct IndexStack {
+    head: AtomicU64,
+}
+
+impl IndexStack {
+    const fn new() -> IndexStack {
+        IndexStack {
+            head: AtomicU64::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn push<T>(&self, slots: &[Slot<T>], index: usize) {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            slots[index]
+                .next
+                .store((head & INDEX_MASK) as usize, Ordering::Relaxed);
+            let new = (index as u64 + 1) | next_generation(head);
+            match self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Pops an index. A stale `next` is harmless, since its slot was popped in between and the
+    /// head's generation has moved on.
+    #[inline(always)]
+    fn pop<T>(&self, slots: &[Slot<T>]) -> Option<usize> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let top = ((head & INDEX_MASK) as usize).checked_sub(1)?;
+            let next = slots[top].next.load(Ordering::Relaxed);
+            let new = next as u64 | next_generation(head);
+            match self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => return Some(top),
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn next_generation(head: u64) -> u64 {
+    (head & !INDEX_MASK).wrapping_add(1 << INDEX_BITS)
+}
+
+/// An `AtomicOption` that stores plain values, taking its boxes from a `BoxPool`.
+pub struct PooledAtomicOption<T> {
+    slot: AtomicOption<T>,
+    pool: BoxPool<T>,
+}
+
+impl<T> PooledAtomicOption<T> {
+    /// Creates an empty slot that retains at most `cap` spare nodes.
+    pub fn new(cap: usize) -> PooledAtomicOption<T> {
+        PooledAtomicOption {
+            slot: AtomicOption::new(None),
+            pool: BoxPool::new(cap),
+        }
+    }
+
+    #[inline(always)]
+    pub fn swap_value(&self, new: Option<T>) -> Option<T> {
+        let new = new.map(|value| self.pool.boxed(value));
+        self.slot.swap(new).map(|data| self.pool.recycle(data))
+    }
+
+    #[inline(always)]
+    pub fn take_value(&self) -> Option<T> {
+        self.swap_value(None)
+    }
+
+    #[inline(always)]
+    pub fn store_value(&self, value: T) {
+        drop(self.swap_value(Some(value)))
+    }
+
+    #[inline(always)]
+    pub fn slot(&self) -> &AtomicOption<T> {
+        &self.slot
+    }
+
+    #[inline(always)]
+    pub fn pool(&self) -> &BoxPool<T> {
+        &self.pool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+
+    #[cfg(feature = "std")]
+    use super::BoxPool;
+    use super::PooledAtomicOption;
+
+    #[test]
+    fn test_recycle() {
+        let opt = PooledAtomicOption::new(1);
+        assert_eq!(opt.take_value(), None);
+        opt.store_value("0".to_string());
+        let addr = opt.slot().load_ptr();
+        assert_eq!(opt.swap_value(Some("1".to_string())), Some("0".to_string()));
+        assert_eq!(opt.pool().retained(), 1);
+        assert_eq!(opt.take_value(), Some("1".to_string()));
+        assert_eq!(opt.pool().retained(), 1);
+        opt.store_value("2".to_string());
+        assert_eq!(opt.slot().load_ptr(), addr);
+        assert_eq!(opt.pool().retained(), 0);
+        opt.store_value("3".to_string());
+        assert_eq!(opt.pool().retained(), 1);
+        assert_eq!(opt.take_value().as_deref(), Some("3"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_threads() {
+        let pool = BoxPool::new(4);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..1000 {
+                        let data = pool.boxed(i.to_string());
+                        assert_eq!(pool.recycle(data), i.to_string());
+                    }
+                });
+            }
+        });
+        assert!(pool.retained() <= 4);
+    }
+
+    #[test]
+    fn test_cap() {
+        let opt = PooledAtomicOption::<String>::new(0);
+        opt.store_value("0".to_string());
+        opt.store_value("1".to_string());
+        assert_eq!(opt.pool().retained(), 0);
+        assert_eq!(opt.take_value().as_deref(), Some("1"));
+    }
+}
@@ -0,0 +1,144 @@
+use alloc::boxed::Box;
+use core::ptr::null;
+
+use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::allocator::BoxAllocator;
+#[cfg(feature = "epoch")]
+use crate::epoch::EpochAtomicOption;
+#[cfg(feature = "std")]
+use crate::hazard::HazardAtomicOption;
+use crate::AtomicOption;
+
+/// Serializes the value as an `Option<T>`.
+///
+/// A plain `AtomicOption` cannot lend out its value, so it is taken out of the slot while it is
+/// serialized and put back afterwards, even if serialization fails or panics. In between, other
+/// threads see the slot empty: a `take` gets nothing and a `try_store` succeeds. The value is only
+/// put back if the slot is still empty, otherwise the newer one is kept and the serialized one is
+/// dropped, as if it had been overwritten. `HazardAtomicOption` and `EpochAtomicOption` serialize
+/// a protected snapshot instead and leave the slot alone.
+impl<T: Serialize, A: BoxAllocator> Serialize for AtomicOption<T, A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let restore = Restore {
+            opt: self,
+            addr: Self::into_raw(self.take()),
+        };
+        unsafe { restore.addr.as_ref() }.serialize(serializer)
+    }
+}
+
+/// Puts a taken value back into its slot on drop, unless the slot was filled in the meantime.
+struct Restore<'a, T, A: BoxAllocator> {
+    opt: &'a AtomicOption<T, A>,
+    addr: *mut T,
+}
+
+impl<T, A: BoxAllocator> Drop for Restore<'_, T, A> {
+    fn drop(&mut self) {
+        let _ = self
+            .opt
+            .compare_exchange(null(), unsafe { self.opt.rebox(self.addr) });
+    }
+}
+
+/// Serializes the value as an `Option<T>` while it is protected by a hazard pointer.
+#[cfg(feature = "std")]
+impl<T: Serialize> Serialize for HazardAtomicOption<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.protect().as_deref().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for HazardAtomicOption<T> {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HazardAtomicOption<T>, D::Error> {
+        Option::<Box<T>>::deserialize(deserializer).map(HazardAtomicOption::new)
+    }
+}
+
+/// Serializes the value as an `Option<T>` while the current thread is pinned.
+#[cfg(feature = "epoch")]
+impl<T: Serialize> Serialize for EpochAtomicOption<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.load(&crossbeam_epoch::pin()).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "epoch")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for EpochAtomicOption<T> {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<EpochAtomicOption<T>, D::Error> {
+        Option::<Box<T>>::deserialize(deserializer).map(EpochAtomicOption::new)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for AtomicOption<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<AtomicOption<T>, D::Error> {
+        Option::<Box<T>>::deserialize(deserializer).map(AtomicOption::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use ::serde::ser::{Error, Serialize, Serializer};
+
+    use crate::AtomicOption;
+
+    #[test]
+    fn test_round_trip() {
+        let opt = AtomicOption::new(Some(Box::new(Vec::from([0, 1]))));
+        assert_eq!(serde_json::to_string(&opt).unwrap(), "[0,1]");
+        assert_eq!(opt.take(), Some(Box::new(Vec::from([0, 1]))));
+        assert_eq!(serde_json::to_string(&opt).unwrap(), "null");
+
+        let opt: AtomicOption<i32> = serde_json::from_str("2").unwrap();
+        assert_eq!(opt.take(), Some(Box::new(2)));
+        let opt: AtomicOption<i32> = serde_json::from_str("null").unwrap();
+        assert!(opt.is_none());
+    }
+
+    struct Failing;
+
+    impl Serialize for Failing {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("failing"))
+        }
+    }
+
+    #[test]
+    fn test_restore_on_error() {
+        let opt = AtomicOption::new(Some(Box::new(Failing)));
+        assert!(serde_json::to_string(&opt).is_err());
+        assert!(opt.is_some());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_hazard() {
+        use crate::hazard::HazardAtomicOption;
+
+        let opt = HazardAtomicOption::new(Some(Box::new(0)));
+        assert_eq!(serde_json::to_string(&opt).unwrap(), "0");
+        assert_eq!(opt.protect().as_deref(), Some(&0));
+        let opt: HazardAtomicOption<i32> = serde_json::from_str("1").unwrap();
+        assert_eq!(opt.take().as_deref(), Some(&1));
+    }
+
+    #[cfg(feature = "epoch")]
+    #[test]
+    fn test_epoch() {
+        use crate::epoch::EpochAtomicOption;
+
+        let opt = EpochAtomicOption::new(Some(Box::new(0)));
+        assert_eq!(serde_json::to_string(&opt).unwrap(), "0");
+        let opt: EpochAtomicOption<i32> = serde_json::from_str("null").unwrap();
+        assert_eq!(serde_json::to_string(&opt).unwrap(), "null");
+    }
+}
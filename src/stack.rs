@@ -0,0 +1,187 @@
+use core::marker::PhantomData;
+use core::ptr::null_mut;
+use std::boxed::Box;
+
+use crate::atomic::{AtomicPtr, Ordering};
+use crate::hazard::{drop_box, Domain};
+use crate::AtomicOption;
+
+/// A lock-free Treiber stack of boxes.
+///
+/// Popped nodes are retired to a hazard pointer `Domain`, which also rules out ABA on `pop`. The
+/// payload of a node is an `AtomicOption`, so the winning `pop` moves it out while other threads
+/// may still be looking at the node.
+pub struct AtomicStack<T> {
+    head: AtomicPtr<Node<T>>,
+    domain: &'static Domain,
+}
+
+struct Node<T> {
+    value: AtomicOption<T>,
+    next: *mut Node<T>,
+}
+
+impl<T> AtomicStack<T> {
+    #[inline(always)]
+    pub fn new() -> AtomicStack<T> {
+        Self::with_domain(Domain::global())
+    }
+
+    #[inline(always)]
+    pub fn with_domain(domain: &'static Domain) -> AtomicStack<T> {
+        AtomicStack {
+            head: AtomicPtr::new(null_mut()),
+            domain,
+        }
+    }
+
+    pub fn push(&self, data: Box<T>) {
+        let node = Box::into_raw(Box::new(Node {
+            value: AtomicOption::new(Some(data)),
+            next: null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::SeqCst, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<Box<T>> {
+        let hazard = self.domain.hazard_pointer();
+        loop {
+            let head = hazard.protect(&self.head);
+            if head.is_null() {
+                return None;
+            }
+            let next = unsafe { (*head).next };
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+            {
+                return unsafe { unlink(head, self.domain) };
+            }
+        }
+    }
+
+    /// Detaches every node at once, yielding the values from the most recently pushed.
+    pub fn take_all(&self) -> TakeAll<'_, T> {
+        TakeAll {
+            node: self.head.swap(null_mut(), Ordering::SeqCst),
+            stack: PhantomData,
+            domain: self.domain,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+}
+
+/// Moves the value out of `node`, which the caller has just unlinked, and retires it.
+#[inline(always)]
+unsafe fn unlink<T>(node: *mut Node<T>, domain: &Domain) -> Option<Box<T>> {
+    let data = (*node).value.take();
+    domain.retire(node.cast(), drop_box::<Node<T>>);
+    data
+}
+
+impl<T> Default for AtomicStack<T> {
+    fn default() -> AtomicStack<T> {
+        AtomicStack::new()
+    }
+}
+
+unsafe impl<T> Sync for AtomicStack<T> where T: Send {}
+unsafe impl<T> Send for AtomicStack<T> where T: Send {}
+
+impl<T> Drop for AtomicStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            let n = unsafe { Box::from_raw(node) };
+            node = n.next;
+        }
+    }
+}
+
+/// Iterator returned by `AtomicStack::take_all`.
+pub struct TakeAll<'a, T> {
+    node: *mut Node<T>,
+    stack: PhantomData<&'a AtomicStack<T>>,
+    domain: &'static Domain,
+}
+
+impl<T> Iterator for TakeAll<'_, T> {
+    type Item = Box<T>;
+
+    fn next(&mut self) -> Option<Box<T>> {
+        if self.node.is_null() {
+            return None;
+        }
+        let node = self.node;
+        self.node = unsafe { (*node).next };
+        unsafe { unlink(node, self.domain) }
+    }
+}
+
+impl<T> Drop for TakeAll<'_, T> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::boxed::Box;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::vec::Vec;
+
+    use super::AtomicStack;
+
+    #[test]
+    fn test_simple() {
+        let stack = AtomicStack::new();
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+        for i in 0..4 {
+            stack.push(Box::new(i));
+        }
+        assert_eq!(stack.pop(), Some(Box::new(3)));
+        let all: Vec<_> = stack.take_all().map(|v| *v).collect();
+        assert_eq!(all, [2, 1, 0]);
+        assert!(stack.is_empty());
+        stack.push(Box::new(4));
+        drop(stack.take_all());
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn test_threads() {
+        let stack = AtomicStack::new();
+        let popped = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..1000 {
+                        stack.push(Box::new(i));
+                        if stack.pop().is_some() {
+                            popped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        let remain = stack.take_all().count();
+        assert_eq!(popped.load(Ordering::Relaxed) + remain, 4000);
+    }
+}
@@ -0,0 +1,224 @@
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use core::mem::align_of;
+use core::ptr::null_mut;
+
+use crate::atomic::{AtomicPtr, Ordering};
+use crate::PhantomUnsync;
+
+/// An `AtomicOption` that packs a `BITS`-bit tag into the low bits freed by `align_of::<T>()`, so
+/// the value and the tag are always updated together.
+pub struct TaggedAtomicOption<T, const BITS: usize> {
+    inner: AtomicPtr<T>,
+    _phantom: PhantomUnsync<T>,
+}
+
+impl<T, const BITS: usize> TaggedAtomicOption<T, BITS> {
+    const MASK: usize = {
+        assert!(
+            BITS <= align_of::<T>().trailing_zeros() as usize,
+            "alignment of T leaves too few spare bits for the tag"
+        );
+        (1 << BITS) - 1
+    };
+
+    /// # Panics
+    ///
+    /// Panics unless `tag` fits in `BITS` bits.
+    #[inline(always)]
+    pub fn new(data: Option<Box<T>>, tag: usize) -> TaggedAtomicOption<T, BITS> {
+        TaggedAtomicOption {
+            inner: AtomicPtr::new(Self::pack(data, tag)),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the current address (null if empty) and tag.
+    ///
+    /// The address is only an identity token for `compare_exchange` and must not be dereferenced.
+    #[inline(always)]
+    pub fn load(&self) -> (*const T, usize) {
+        Self::unpack(self.inner.load(Ordering::Acquire))
+    }
+
+    #[inline(always)]
+    pub fn load_tag(&self) -> usize {
+        self.load().1
+    }
+
+    /// Replaces both the value and the tag, returning the old pair.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `tag` fits in `BITS` bits.
+    #[inline(always)]
+    pub fn swap_with_tag(&self, new: Option<Box<T>>, tag: usize) -> (Option<Box<T>>, usize) {
+        let packed = self.inner.swap(Self::pack(new, tag), Ordering::AcqRel);
+        let (addr, tag) = Self::unpack(packed);
+        (unsafe { from_raw(addr as *mut T) }, tag)
+    }
+
+    /// Replaces the value, keeping the tag.
+    #[inline(always)]
+    pub fn swap(&self, new: Option<Box<T>>) -> Option<Box<T>> {
+        let new = new.map_or(null_mut(), Box::into_raw);
+        let packed = self
+            .inner
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |packed| {
+                Some(new.map_addr(|addr| addr | (packed.addr() & Self::MASK)))
+            })
+            .unwrap();
+        unsafe { from_raw(Self::unpack(packed).0 as *mut T) }
+    }
+
+    #[inline(always)]
+    pub fn take(&self) -> Option<Box<T>> {
+        self.swap(None)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, new: Option<Box<T>>) {
+        drop(self.swap(new))
+    }
+
+    /// Updates the tag alone with `f`, mirroring `AtomicUsize::fetch_update`.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless every tag returned by `f` fits in `BITS` bits.
+    pub fn fetch_tag_update<F>(&self, mut f: F) -> Result<usize, usize>
+    where
+        F: FnMut(usize) -> Option<usize>,
+    {
+        self.inner
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |packed| {
+                let tag = f(packed.addr() & Self::MASK)?;
+                Self::check_tag(tag);
+                Some(packed.map_addr(|addr| (addr & !Self::MASK) | tag))
+            })
+            .map(|packed| packed.addr() & Self::MASK)
+            .map_err(|packed| packed.addr() & Self::MASK)
+    }
+
+    /// Stores `new` if the slot still holds the `current` address and tag.
+    ///
+    /// On success the previous value is returned, on failure the new value is handed back.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless both tags fit in `BITS` bits.
+    #[allow(clippy::type_complexity)]
+    pub fn compare_exchange(
+        &self,
+        current: (*const T, usize),
+        new: (Option<Box<T>>, usize),
+    ) -> Result<Option<Box<T>>, Option<Box<T>>> {
+        Self::check_tag(current.1);
+        let current = (current.0 as *mut T).map_addr(|addr| addr | current.1);
+        let new = Self::pack(new.0, new.1);
+        match self
+            .inner
+            .compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(old) => Ok(unsafe { from_raw(Self::unpack(old).0 as *mut T) }),
+            Err(_) => Err(unsafe { from_raw(Self::unpack(new).0 as *mut T) }),
+        }
+    }
+
+    #[inline(always)]
+    fn pack(data: Option<Box<T>>, tag: usize) -> *mut T {
+        Self::check_tag(tag);
+        let addr = data.map_or(null_mut(), Box::into_raw);
+        addr.map_addr(|addr| addr | tag)
+    }
+
+    #[inline(always)]
+    fn check_tag(tag: usize) {
+        assert!(tag <= Self::MASK, "tag does not fit in {BITS} bits");
+    }
+
+    #[inline(always)]
+    fn unpack(packed: *mut T) -> (*const T, usize) {
+        (
+            packed.map_addr(|addr| addr & !Self::MASK),
+            packed.addr() & Self::MASK,
+        )
+    }
+}
+
+unsafe impl<T, const BITS: usize> Sync for TaggedAtomicOption<T, BITS> where T: Send {}
+unsafe impl<T, const BITS: usize> Send for TaggedAtomicOption<T, BITS> where T: Send {}
+
+impl<T, const BITS: usize> Drop for TaggedAtomicOption<T, BITS> {
+    fn drop(&mut self) {
+        let addr = Self::unpack(*self.inner.get_mut()).0;
+        drop(unsafe { from_raw(addr as *mut T) });
+    }
+}
+
+#[inline(always)]
+unsafe fn from_raw<T>(addr: *mut T) -> Option<Box<T>> {
+    if addr.is_null() {
+        None
+    } else {
+        Some(Box::from_raw(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use core::ptr::null;
+
+    use super::TaggedAtomicOption;
+
+    #[test]
+    fn test_simple() {
+        let opt = TaggedAtomicOption::<u64, 3>::new(None, 5);
+        assert_eq!(opt.load(), (null(), 5));
+        assert_eq!(opt.swap(Some(Box::new(0))), None);
+        assert_eq!(opt.load_tag(), 5);
+        assert_eq!(
+            opt.swap_with_tag(Some(Box::new(1)), 2),
+            (Some(Box::new(0)), 5)
+        );
+        assert_eq!(opt.fetch_tag_update(|tag| Some(tag + 1)), Ok(2));
+        assert_eq!(opt.fetch_tag_update(|_| None), Err(3));
+        assert_eq!(opt.take(), Some(Box::new(1)));
+        assert_eq!(opt.load(), (null(), 3));
+    }
+
+    #[test]
+    fn test_compare_exchange() {
+        let opt = TaggedAtomicOption::<u32, 2>::new(Some(Box::new(0)), 1);
+        let (addr, tag) = opt.load();
+        assert_eq!(
+            opt.compare_exchange((addr, 0), (Some(Box::new(1)), 2)),
+            Err(Some(Box::new(1)))
+        );
+        assert_eq!(
+            opt.compare_exchange((addr, tag), (Some(Box::new(1)), 2)),
+            Ok(Some(Box::new(0)))
+        );
+        assert_eq!(opt.load_tag(), 2);
+        assert_eq!(
+            opt.compare_exchange((opt.load().0, 2), (None, 0)),
+            Ok(Some(Box::new(1)))
+        );
+        assert_eq!(opt.load(), (null(), 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "tag does not fit in 3 bits")]
+    fn test_tag_overflow() {
+        let opt = TaggedAtomicOption::<u64, 3>::new(None, 7);
+        opt.swap_with_tag(None, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "tag does not fit in 2 bits")]
+    fn test_tag_update_overflow() {
+        let opt = TaggedAtomicOption::<u32, 2>::new(None, 3);
+        let _ = opt.fetch_tag_update(|tag| Some(tag + 1));
+    }
+}
@@ -0,0 +1,123 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use crate::atomic::{AtomicUsize, Ordering};
+use crate::AtomicOption;
+
+const INDEX: usize = 0b11;
+const FRESH: usize = 0b100;
+
+/// Creates a single-producer single-consumer triple buffer whose reader starts out on `initial`.
+///
+/// Each side owns one of three `AtomicOption` slots at a time and they trade slots through a
+/// shared index, so the writer never waits for the reader and the reader never sees a partial
+/// frame.
+pub fn new<T>(initial: Box<T>) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        slots: [
+            AtomicOption::new(Some(initial)),
+            AtomicOption::new(None),
+            AtomicOption::new(None),
+        ],
+        middle: AtomicUsize::new(1),
+    });
+    (
+        Writer {
+            shared: shared.clone(),
+            back: 2,
+        },
+        Reader { shared, front: 0 },
+    )
+}
+
+struct Shared<T> {
+    slots: [AtomicOption<T>; 3],
+    middle: AtomicUsize,
+}
+
+/// The producing half of a triple buffer.
+pub struct Writer<T> {
+    shared: Arc<Shared<T>>,
+    back: usize,
+}
+
+impl<T> Writer<T> {
+    /// Publishes `frame` as the latest one.
+    ///
+    /// Returns a previously published frame that is no longer in use, so it can be refilled
+    /// instead of allocating a new one.
+    pub fn publish(&mut self, frame: Box<T>) -> Option<Box<T>> {
+        let old = self.shared.slots[self.back].swap(Some(frame));
+        self.back = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel) & INDEX;
+        old
+    }
+}
+
+/// The consuming half of a triple buffer.
+pub struct Reader<T> {
+    shared: Arc<Shared<T>>,
+    front: usize,
+}
+
+impl<T> Reader<T> {
+    /// Whether a frame was published since the last call to `latest`.
+    #[inline(always)]
+    pub fn has_update(&self) -> bool {
+        self.shared.middle.load(Ordering::Relaxed) & FRESH != 0
+    }
+
+    /// Borrows the most recently published frame, or the initial one if nothing was published.
+    pub fn latest(&mut self) -> &T {
+        if self.has_update() {
+            self.front = self.shared.middle.swap(self.front, Ordering::AcqRel) & INDEX;
+        }
+        let addr = self.shared.slots[self.front].load_ptr();
+        // The front slot is only ever filled while owned by the writer and is not touched by
+        // it again until the reader hands it back.
+        unsafe { &*addr }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    #[cfg(feature = "std")]
+    use std::thread;
+
+    #[test]
+    fn test_reuse() {
+        let (mut writer, mut reader) = super::new(Box::new(0));
+        assert!(!reader.has_update());
+        assert_eq!(*reader.latest(), 0);
+        assert_eq!(writer.publish(Box::new(1)), None);
+        assert_eq!(writer.publish(Box::new(2)), None);
+        assert!(reader.has_update());
+        assert_eq!(*reader.latest(), 2);
+        assert_eq!(*reader.latest(), 2);
+        assert_eq!(writer.publish(Box::new(3)), Some(Box::new(1)));
+        assert_eq!(writer.publish(Box::new(4)), Some(Box::new(0)));
+        assert_eq!(*reader.latest(), 4);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_threads() {
+        let (mut writer, mut reader) = super::new(Box::new([0u64; 16]));
+        let h = thread::spawn(move || {
+            let mut last = 0;
+            while last < 1000 {
+                let frame = reader.latest();
+                assert!(frame.iter().all(|x| *x == frame[0]));
+                assert!(frame[0] >= last);
+                last = frame[0];
+            }
+        });
+        let mut spare = None;
+        for i in 1..=1000 {
+            let mut frame = spare.take().unwrap_or_else(|| Box::new([0; 16]));
+            frame.fill(i);
+            spare = writer.publish(frame);
+        }
+        h.join().unwrap();
+    }
+}